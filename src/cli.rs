@@ -1,15 +1,36 @@
-use clap::Parser;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
 
 // Descriptions of the arguments are mostly verbatim-copied from
 // the `concurrently` project, which is MIT licensed and
 // Copyright (c) by Kimmo Brunfeldt (and possible contributors)
 
 #[derive(Parser, Debug, Default, PartialEq)]
-#[clap(about, version, author)]
+#[clap(
+    about,
+    version,
+    author,
+    args_conflicts_with_subcommands = true,
+    disable_help_subcommand = true
+)]
 pub struct Args {
-    #[clap(global = true)]
     pub commands: Vec<String>,
 
+    #[clap(subcommand)]
+    pub subcommand: Option<SubCommand>,
+
+    /// Path to a config file (TOML) defining the commands
+    /// to run and their options. If not given, a file named
+    /// "rly.toml" in the current directory is used, if it
+    /// exists. Options and commands given on the command
+    /// line take precedence over those from the file. Flags
+    /// enabled in the file can be disabled again using
+    /// --no-<flag> (e.g. --no-kill-others), and --no-color
+    /// using --color.
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+
     /// List of custom names to be used in prefix template.
     ///
     /// Example names: "main,browser,server"
@@ -28,34 +49,123 @@ pub struct Args {
     #[clap(short, long)]
     pub raw: bool,
 
+    /// Attach processes to a pseudo-terminal instead of
+    /// pipes, so that they keep their colors and progress
+    /// bars. Their stdout and stderr can no longer be told
+    /// apart, and of lines redrawn using carriage returns,
+    /// only the final state is shown. Ignored for --raw.
+    #[clap(long)]
+    pub pty: bool,
+
     /// Disables colors from logging.
     #[clap(long, default_value = "false")]
     pub no_color: bool,
 
+    /// Print the stderr of processes to stdout, instead of
+    /// forwarding it to the stderr of rly. Grouped output
+    /// and JSON output are always printed to stdout.
+    #[clap(long)]
+    pub merge_stderr: bool,
+
+    /// Whether input should be forwarded to the processes.
+    /// Lines starting with the name or index of a process
+    /// and a colon (e.g. "server:rs") are sent to that
+    /// process, all others to --default-input-target.
+    #[clap(short = 'i', long)]
+    pub handle_input: bool,
+
+    /// Name or index of the process that input is sent to
+    /// if it does not start with a process.
+    ///
+    /// [default: 0]
+    #[clap(long, value_name = "TARGET")]
+    pub default_input_target: Option<String>,
+
     /// Comma-separated list of processes for which to
     /// hide the output. The processes can be identified
     /// by their name or index (if no names are used).
     #[clap(long)]
     pub hide: Option<String>,
 
-    // Order the output as if the commands were run sequentially.
-    // #[clap(short, long)]
-    // group: bool,
+    /// Buffer the output of each process and print it in
+    /// one block once the process exited, instead of
+    /// interleaving the output of all processes.
+    #[clap(short, long)]
+    pub group: bool,
+
+    /// Order in which the output blocks of --group are
+    /// printed. With "exit", output is printed as soon as a
+    /// process exited. With "command", output is ordered as
+    /// if the commands were run sequentially.
+    ///
+    /// [default: exit]
+    #[clap(long, value_name = "ORDER")]
+    pub group_order: Option<String>,
+
+    /// Format of the output. With "jsonl", every line of
+    /// output, spawn, restart and exit is printed as a JSON
+    /// object on a line of its own, with the fields
+    /// timestamp, event, name, index, pid, stream, line,
+    /// exit_code, signal and message. Can't be combined with
+    /// --raw.
+    ///
+    /// Possible values: text, jsonl
+    ///
+    /// [default: text]
+    #[clap(long, value_name = "FORMAT")]
+    pub output_format: Option<String>,
+
+    /// Show timing information for all processes in a
+    /// table once all of them exited.
+    #[clap(long)]
+    pub timings: bool,
 
-    // Show timing information for all processes.
-    // #[clap(long)]
-    // pub timings: bool,
+    /// Show an interactive terminal UI instead of printing
+    /// the output, which lists all processes along with
+    /// their status and shows the output of the selected
+    /// one. Processes can be restarted (r), stopped (x) and
+    /// started (s) from there. Quit using q or Ctrl-C.
+    #[clap(long)]
+    pub tui: bool,
+
+    /// Accept requests on a Unix domain socket while running,
+    /// which allows processes to be listed, started, stopped,
+    /// restarted and signaled, and their output to be followed.
+    #[clap(long)]
+    pub control: bool,
+
+    /// Path of the socket of --control. Implies --control.
+    /// Required to run more than one instance with --control.
+    ///
+    /// [default: $XDG_RUNTIME_DIR/rly.sock]
+    #[clap(long, value_name = "PATH")]
+    pub control_socket: Option<PathBuf>,
+
+    /// Passthrough additional arguments to commands
+    /// (accessible via placeholders) instead of treating
+    /// them as commands.
+    ///
+    /// Example: rly -P "jest {1}" "eslint {@}" -- src/foo.ts
+    ///
+    /// Placeholders: {1}, {2}, ... for single arguments,
+    /// {@} for all arguments (quoted separately) and {*}
+    /// for all arguments (quoted as a single string)
+    #[clap(short = 'P', long)]
+    pub passthrough_arguments: bool,
+
+    /// Arguments given after `--`. These are commands, unless
+    /// [`Args::passthrough_arguments`] is set.
+    #[clap(last = true, hide = true)]
+    pub additional_arguments: Vec<String>,
 
-    // Passthrough additional arguments to commands
-    // (accessible via placeholders) instead of treating
-    // them as commands.
-    // #[clap(short = 'P', long)]
-    // pub passthrough_arguments: bool,
     /// Prefix used in logging for each process.
     /// Possible values: index, pid, time, command, name,
-    /// none, or a template. Example template: "{time}-{pid}"
-    #[clap(short, long, default_value = "[{name}]")]
-    pub prefix: String,
+    /// stream, none, or a template. Example template:
+    /// "{time}-{pid}"
+    ///
+    /// [default: "[{name}]"]
+    #[clap(short, long)]
+    pub prefix: Option<String>,
 
     /// Comma-separated list of chalk colors to use on
     /// prefixes. If there are more commands than colors, the
@@ -73,18 +183,36 @@ pub struct Args {
     ///
     /// See <https://www.npmjs.com/package/chalk> for more
     /// information.
-    #[clap(short = 'c', long, default_value = "reset")]
-    pub prefix_colors: String,
+    ///
+    /// [default: reset]
+    #[clap(short = 'c', long)]
+    pub prefix_colors: Option<String>,
+
+    /// Comma-separated list of two chalk colors to use on
+    /// the lines of processes, the first one for stdout and
+    /// the second one for stderr. If only one color is
+    /// given, it is used for both. The available colors are
+    /// the same as for --prefix-colors.
+    ///
+    /// Example: "reset,dim.red"
+    ///
+    /// [default: reset]
+    #[clap(long, value_name = "COLORS")]
+    pub stream_colors: Option<String>,
 
     /// Limit how many characters of the command is displayed
     /// in prefix. The option can be used to shorten the
     /// prefix when it is set to "command"
-    #[clap(short = 'l', long, default_value = "10")]
-    pub prefix_length: usize,
+    ///
+    /// [default: 10]
+    #[clap(short = 'l', long)]
+    pub prefix_length: Option<usize>,
 
     /// Specify the timestamp in chrono::format syntax.
-    #[clap(short, long, default_value = "%Y-%m-%d %H:%M:%S.%3f")]
-    pub timestamp_format: String,
+    ///
+    /// [default: "%Y-%m-%d %H:%M:%S.%3f"]
+    #[clap(short, long)]
+    pub timestamp_format: Option<String>,
 
     /// Kill other processes if one exits or dies.
     #[clap(short, long)]
@@ -95,14 +223,255 @@ pub struct Args {
     #[clap(long)]
     pub kill_others_on_fail: bool,
 
+    /// Signal that processes are terminated with, e.g. when
+    /// pressing Ctrl-C or because of --kill-others. Processes
+    /// that are still running after --kill-timeout are sent
+    /// SIGKILL. Can also be a sequence of signals and delays,
+    /// e.g. "SIGINT,10s,SIGTERM,5s,SIGKILL".
+    ///
+    /// [default: SIGTERM]
+    #[clap(long, value_name = "SIGNAL")]
+    pub kill_signal: Option<String>,
+
+    /// Time to wait for processes to exit after --kill-signal
+    /// before sending SIGKILL, in milliseconds.
+    ///
+    /// [default: 2000]
+    #[clap(long, value_name = "MS")]
+    pub kill_timeout: Option<u64>,
+
+    /// Comma-separated list of processes that SIGHUP, SIGUSR1,
+    /// SIGUSR2 and SIGQUIT are forwarded to when rly receives
+    /// them, by name or index. SIGTERM terminates all processes
+    /// like Ctrl-C does.
+    ///
+    /// [default: all processes]
+    #[clap(long, value_name = "TARGETS")]
+    pub signal_targets: Option<String>,
+
+    /// Act as the init process, e.g. as the entrypoint of a
+    /// container: reap orphaned processes and adopt the
+    /// processes left behind by commands. Enabled
+    /// automatically when rly runs as PID 1. Linux only.
+    #[clap(long)]
+    pub init: bool,
+
+    /// When to restart processes that exited. With
+    /// "on-failure", processes that exited with a non-zero
+    /// code are restarted, with "always" all of them. With
+    /// "unless-stopped", processes are restarted unless
+    /// they have been stopped by a signal (e.g. using kill).
+    ///
+    /// Possible values: on-failure, always, never,
+    /// unless-stopped
+    ///
+    /// [default: on-failure]
+    #[clap(long, value_name = "POLICY")]
+    pub restart_policy: Option<String>,
+
     /// How many times a process that died should restart.
     /// Negative numbers will make the process restart forever.
-    #[clap(long, default_value = "0")]
-    pub restart_tries: i32,
+    ///
+    /// [default: 0, or forever if --restart-policy is given]
+    #[clap(long)]
+    pub restart_tries: Option<i32>,
 
     /// Delay time to respawn the process, in milliseconds.
-    #[clap(long, default_value = "0")]
-    pub restart_after: u64,
+    ///
+    /// [default: 0]
+    #[clap(long)]
+    pub restart_after: Option<u64>,
+
+    /// Factor that the delay of --restart-after grows by with
+    /// every consecutive restart, e.g. 2 for exponential
+    /// backoff.
+    ///
+    /// [default: 1]
+    #[clap(long, value_name = "FACTOR")]
+    pub restart_backoff: Option<f64>,
+
+    /// Maximum delay that --restart-backoff grows the delay
+    /// to, in milliseconds.
+    #[clap(long, value_name = "MS")]
+    pub restart_max_delay: Option<u64>,
+
+    /// Randomly vary the delay of each restart by up to
+    /// this fraction, e.g. 0.1 for +/- 10%. This keeps
+    /// processes that failed together from restarting at
+    /// the same time.
+    ///
+    /// [default: 0]
+    #[clap(long, value_name = "FRACTION")]
+    pub restart_jitter: Option<f64>,
+
+    /// Once a process has been running for this long (in
+    /// milliseconds), its restart tries and backoff start
+    /// over the next time it exits.
+    #[clap(long, value_name = "MS")]
+    pub restart_reset_after: Option<u64>,
+
+    /// Stop restarting a process once it failed this many
+    /// times within --crash-loop-window, as it is probably
+    /// stuck in a crash loop. Every exit counts, even with
+    /// exit code 0, unless the process has been running for
+    /// --restart-reset-after.
+    #[clap(long, value_name = "N")]
+    pub crash_loop_failures: Option<usize>,
+
+    /// Time window for --crash-loop-failures, in
+    /// milliseconds.
+    ///
+    /// [default: 30000]
+    #[clap(long, value_name = "MS")]
+    pub crash_loop_window: Option<u64>,
+
+    /// Kill other processes if one is stuck in a crash loop,
+    /// see --crash-loop-failures.
+    #[clap(long)]
+    pub kill_others_on_crash_loop: bool,
+
+    /// Which command(s) must exit successfully for rly to
+    /// exit with code 0. Otherwise, the exit code of the
+    /// relevant failed command is used (128 + signal number
+    /// for commands terminated by a signal).
+    ///
+    /// Possible values: first, last, all, command-{name|index},
+    /// !command-{name|index}
+    ///
+    /// [default: all]
+    #[clap(short, long)]
+    pub success: Option<String>,
+
+    /// Restart processes when files matching this glob
+    /// (relative to the working directory of the process)
+    /// change. Can be given multiple times. Files ignored
+    /// by .gitignore or .ignore are never watched.
+    /// Processes that exit keep being watched, and are
+    /// started again on the next change.
+    #[clap(long, value_name = "GLOB")]
+    pub watch: Vec<String>,
+
+    /// Signal that tells a watched process about changed
+    /// files. With SIGTERM, the process is terminated and
+    /// started again. With SIGHUP, the process is expected
+    /// to reload itself.
+    ///
+    /// [default: SIGTERM]
+    #[clap(long, value_name = "SIGNAL")]
+    pub watch_signal: Option<String>,
+
+    // Counterparts of the flags above, which turn off what a config file
+    // turned on. Whichever of a flag and its counterpart is given last wins.
+    #[clap(long, overrides_with = "no_color", hide = true)]
+    pub color: bool,
+
+    #[clap(long, overrides_with = "raw", hide = true)]
+    pub no_raw: bool,
+
+    #[clap(long, overrides_with = "pty", hide = true)]
+    pub no_pty: bool,
+
+    #[clap(long, overrides_with = "merge_stderr", hide = true)]
+    pub no_merge_stderr: bool,
+
+    #[clap(long, overrides_with = "handle_input", hide = true)]
+    pub no_handle_input: bool,
+
+    #[clap(long, overrides_with = "group", hide = true)]
+    pub no_group: bool,
+
+    #[clap(long, overrides_with = "timings", hide = true)]
+    pub no_timings: bool,
+
+    #[clap(long, overrides_with = "tui", hide = true)]
+    pub no_tui: bool,
+
+    #[clap(long, overrides_with = "control", hide = true)]
+    pub no_control: bool,
+
+    #[clap(long, overrides_with = "passthrough_arguments", hide = true)]
+    pub no_passthrough_arguments: bool,
+
+    #[clap(long, overrides_with = "kill_others", hide = true)]
+    pub no_kill_others: bool,
+
+    #[clap(long, overrides_with = "kill_others_on_fail", hide = true)]
+    pub no_kill_others_on_fail: bool,
+
+    #[clap(long, overrides_with = "init", hide = true)]
+    pub no_init: bool,
+
+    #[clap(long, overrides_with = "kill_others_on_crash_loop", hide = true)]
+    pub no_kill_others_on_crash_loop: bool,
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+pub enum SubCommand {
+    /// Control an instance of rly that runs with --control
+    Ctl(CtlArgs),
+}
+
+#[derive(clap::Args, Debug, PartialEq)]
+pub struct CtlArgs {
+    /// Path of the socket of the instance to control.
+    ///
+    /// [default: $XDG_RUNTIME_DIR/rly.sock]
+    #[clap(long, value_name = "PATH")]
+    pub socket: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    pub command: CtlCommand,
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+pub enum CtlCommand {
+    /// List all processes along with their status
+    Status,
+
+    /// Start processes that exited or have been stopped
+    Start(Targets),
+
+    /// Stop processes, without restarting them
+    Stop(Targets),
+
+    /// Restart processes
+    Restart(Targets),
+
+    /// Send a signal to processes, e.g. SIGHUP
+    Signal {
+        signal: String,
+
+        #[clap(flatten)]
+        targets: Targets,
+    },
+
+    /// Print the most recent output of a process, or
+    /// of all of them
+    Logs {
+        /// Name or index of the process
+        target: Option<String>,
+
+        /// Keep printing new output
+        #[clap(short, long)]
+        follow: bool,
+
+        /// Number of lines to print
+        ///
+        /// [default: all lines that have been kept]
+        #[clap(short = 'n', long)]
+        lines: Option<usize>,
+    },
+}
+
+#[derive(clap::Args, Debug, PartialEq)]
+pub struct Targets {
+    /// Names or indices of the processes
+    #[clap(required_unless_present = "all")]
+    pub targets: Vec<String>,
+
+    /// Apply to all processes
+    #[clap(long, conflicts_with = "targets")]
+    pub all: bool,
 }
 
 #[cfg(test)]
@@ -128,4 +497,67 @@ mod tests {
         assert_eq!(commands, res.commands);
         assert!(res.no_color);
     }
+
+    #[test]
+    fn test_additional_arguments() {
+        let res = try_parse(&["-P", "jest {1}", "--", "src/foo.ts", "-u"]).unwrap();
+
+        assert_eq!(vec!["jest {1}".to_string()], res.commands);
+        assert_eq!(
+            vec!["src/foo.ts".to_string(), "-u".to_string()],
+            res.additional_arguments
+        );
+        assert!(res.passthrough_arguments);
+    }
+
+    #[test]
+    fn test_disabling_flags() {
+        let res = try_parse(&["--raw", "--no-raw", "--no-color", "--color", "ls"]).unwrap();
+        assert!(!res.raw && res.no_raw);
+        assert!(!res.no_color && res.color);
+
+        let res = try_parse(&["--no-kill-others", "--kill-others", "ls"]).unwrap();
+        assert!(res.kill_others && !res.no_kill_others);
+    }
+
+    #[test]
+    fn test_ctl() {
+        let res = try_parse(&["ctl", "restart", "api", "web"]).unwrap();
+        assert_eq!(
+            Some(SubCommand::Ctl(CtlArgs {
+                socket: None,
+                command: CtlCommand::Restart(Targets {
+                    targets: vec!["api".to_string(), "web".to_string()],
+                    all: false,
+                }),
+            })),
+            res.subcommand
+        );
+        assert!(res.commands.is_empty());
+
+        let res = try_parse(&["ctl", "--socket", "rly.sock", "stop", "--all"]).unwrap();
+        assert_eq!(
+            Some(SubCommand::Ctl(CtlArgs {
+                socket: Some(PathBuf::from("rly.sock")),
+                command: CtlCommand::Stop(Targets {
+                    targets: vec![],
+                    all: true,
+                }),
+            })),
+            res.subcommand
+        );
+
+        assert!(try_parse(&["ctl", "stop"]).is_err());
+        assert!(try_parse(&["ctl", "stop", "--all", "api"]).is_err());
+
+        // Commands can still be given without a subcommand, and
+        // after options, ctl is a command like any other
+        let res = try_parse(&["npm start", "ctl status"]).unwrap();
+        assert_eq!(vec!["npm start", "ctl status"], res.commands);
+        assert_eq!(None, res.subcommand);
+
+        let res = try_parse(&["--raw", "ctl", "status"]).unwrap();
+        assert_eq!(vec!["ctl", "status"], res.commands);
+        assert_eq!(None, res.subcommand);
+    }
 }
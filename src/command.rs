@@ -1,12 +1,24 @@
 use std::fmt::Formatter;
+use std::path::PathBuf;
 use std::process::Stdio;
-use std::sync::atomic::{AtomicI32, AtomicU32, Ordering};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::{Context, Result};
-use tokio::process::Command as TokioCommand;
+use anyhow::{bail, Context, Result};
+use tokio::process::{Child, Command as TokioCommand};
 
 use crate::colors::colorize;
+use crate::command_spec::CommandSpec;
 use crate::config::Config;
+use crate::dependencies::{check_for_cycles, Dependency};
+use crate::kill::KillSequence;
+use crate::output::{OutputFormat, Stream};
+use crate::pty::Pty;
+use crate::readiness::Readiness;
+use crate::reaper;
+use crate::restart::{CrashLoop, Restart, RestartPolicy};
+use crate::watch::Watch;
 
 /// Holds the information needed to spawn a single process
 /// and format its output.
@@ -21,6 +33,15 @@ pub struct Command {
     /// will not be displayed at all.
     pub hide: bool,
 
+    /// See [`crate::cli::Args::pty`]
+    pub pty: bool,
+
+    /// See [`crate::cli::Args::handle_input`]
+    pub handle_input: bool,
+
+    /// See [`crate::cli::Args::tui`]
+    pub tui: bool,
+
     /// The full command to be executed, including all arguments.
     /// E.g. `"cat some-file | wc -l"`
     pub command: String,
@@ -30,18 +51,41 @@ pub struct Command {
     /// - this value can change, e.g. in case a process is restarted
     pub pid: AtomicU32,
 
-    /// If this flag is set, the process will always be restarted
-    /// irrespective of the value in [`Command::restart_tries`].
-    pub restart_indefinitely: bool,
+    /// Whether and when the process is restarted once it exited
+    pub restart: Restart,
+
+    /// How the process is terminated, see [`crate::cli::Args::kill_signal`]
+    pub kill: KillSequence,
 
-    /// See [`crate::cli::Args::restart_tries`]
-    pub restart_tries: AtomicI32,
+    /// See [`crate::cli::Args::signal_targets`]
+    pub forward_signals: bool,
+
+    /// See [`crate::cli::Args::kill_others`]
+    pub kill_others: bool,
+
+    /// See [`crate::cli::Args::kill_others_on_fail`]
+    pub kill_others_on_fail: bool,
 
     /// See [`crate::cli::Args::prefix`]
     prefix: String,
 
     /// See [`crate::cli::Args::timestamp_format`]
     timestamp_format: String,
+
+    /// Working directory of the spawned process. If not set,
+    /// the working directory of `rly` is used.
+    pub cwd: Option<PathBuf>,
+
+    /// Commands that have to be ready (or completed) before this
+    /// command is spawned.
+    pub depends_on: Vec<Dependency>,
+
+    /// Checks that decide when the command is ready. Commands without
+    /// any checks are ready as soon as they have been spawned.
+    pub readiness: Option<Readiness>,
+
+    /// Files whose changes restart (or reload) the command
+    pub watch: Option<Arc<Watch>>,
 }
 
 impl std::fmt::Display for Command {
@@ -55,7 +99,9 @@ impl std::fmt::Display for Command {
 }
 
 impl Command {
-    pub fn prefix(&self) -> String {
+    /// The prefix for a line read from the given stream. Messages about
+    /// the command itself count as [`Stream::Stdout`].
+    pub fn prefix(&self, stream: Stream) -> String {
         self.prefix
             .replace(
                 "{time}",
@@ -64,6 +110,22 @@ impl Command {
                     .to_string(),
             )
             .replace("{pid}", &self.pid.load(Ordering::Relaxed).to_string())
+            .replace("{stream}", &stream.to_string())
+    }
+
+    /// Spawns the process. If [`Command::pty`] is set, the pseudo-terminal
+    /// it is attached to is returned as well.
+    pub fn spawn(&self) -> Result<(Child, Option<Pty>)> {
+        let mut runnable = self.tokio_command();
+        let pty = if self.pty {
+            Some(Pty::attach(&mut runnable)?)
+        } else {
+            None
+        };
+
+        let child = reaper::spawn(&mut runnable)
+            .with_context(|| format!("Failed to spawn {}", self.command))?;
+        Ok((child, pty))
     }
 
     pub fn tokio_command(&self) -> TokioCommand {
@@ -72,13 +134,30 @@ impl Command {
         // Spawn command in a new process group (0). Pressing Ctrl-C in the
         // parent sends `SIGINT` to all processes in the current foreground
         // process group. rly installs its own Ctrl-C handler to terminate
-        // child processes with the `SIGTERM` signal.
-        runnable.process_group(0).arg("-c").arg(&self.command);
+        // child processes with their kill signal, which is sent to the whole
+        // group, so that processes started by the command are terminated as
+        // well. Commands attached to a pseudo-terminal get a session (and
+        // process group) of their own instead, see [`Pty::attach`].
+        if !self.pty {
+            runnable.process_group(0);
+        }
+        runnable.arg("-c").arg(&self.command);
+
+        if let Some(cwd) = &self.cwd {
+            runnable.current_dir(cwd);
+        }
 
-        if !self.raw {
+        if !self.raw && !self.pty {
             runnable.stdout(Stdio::piped()).stderr(Stdio::piped());
         }
 
+        if self.handle_input && !self.pty {
+            runnable.stdin(Stdio::piped());
+        } else if self.tui && !self.pty {
+            // Key presses are meant for the terminal UI
+            runnable.stdin(Stdio::null());
+        }
+
         runnable
     }
 
@@ -90,6 +169,7 @@ impl Command {
         // -1 because of the two-character ellipsis (..), one
         // character for each part.
         const ELLIPSIS: &str = "..";
+        #[allow(clippy::manual_is_multiple_of)]
         const ELLIPSIS_LENGTH: usize = {
             if ELLIPSIS.len() % 2 != 0 {
                 panic!("Ellipsis length must be even");
@@ -123,44 +203,204 @@ impl Command {
 pub struct Commands;
 
 impl Commands {
-    pub fn from(config: &Config, commands: &[String]) -> Result<Vec<Command>> {
-        let commands: Vec<Command> = commands
+    pub fn from(config: &Config, specs: &[CommandSpec]) -> Result<Vec<Arc<Command>>> {
+        let commands: Vec<Arc<Command>> = specs
             .iter()
             .enumerate()
-            .map(|(idx, cmd)| Self::prepare_command(config, idx, cmd))
+            .map(|(idx, spec)| Self::prepare_command(config, idx, spec).map(Arc::new))
             .collect::<Result<Vec<_>>>()
-            .with_context(|| format!("Failed to parse given commands: {:?}", commands))?;
+            .with_context(|| {
+                let commands: Vec<_> = specs.iter().map(|spec| &spec.command).collect();
+                format!("Failed to parse given commands: {:?}", commands)
+            })?;
+
+        let dependencies: Vec<_> = commands.iter().map(|cmd| cmd.depends_on.clone()).collect();
+        check_for_cycles(&dependencies, &config.names)?;
 
         Ok(commands)
     }
 
-    fn prepare_command(config: &Config, idx: usize, cmd: impl AsRef<str>) -> Result<Command> {
+    fn restart(config: &Config, spec: &CommandSpec) -> Result<Restart> {
+        let policy = match &spec.restart_policy {
+            Some(policy) => Some(policy.parse()?),
+            None => config.restart_policy,
+        };
+
+        // Restarts used to be enabled by giving the number of tries, which
+        // is still the default. Giving a policy restarts indefinitely.
+        let tries = spec
+            .restart_tries
+            .or(config.restart_tries)
+            .unwrap_or(if policy.is_some() { -1 } else { 0 });
+
+        let restart = Restart {
+            policy: policy.unwrap_or(RestartPolicy::OnFailure),
+            tries,
+            delay: spec
+                .restart_after
+                .map(Duration::from_millis)
+                .unwrap_or(config.restart_after),
+            backoff: spec.restart_backoff.unwrap_or(config.restart_backoff),
+            max_delay: spec
+                .restart_max_delay
+                .map(Duration::from_millis)
+                .or(config.restart_max_delay),
+            jitter: spec.restart_jitter.unwrap_or(config.restart_jitter),
+            reset_after: spec
+                .restart_reset_after
+                .map(Duration::from_millis)
+                .or(config.restart_reset_after),
+            crash_loop: spec
+                .crash_loop_failures
+                .or(config.crash_loop_failures)
+                .map(|failures| CrashLoop {
+                    failures,
+                    window: spec
+                        .crash_loop_window
+                        .map(Duration::from_millis)
+                        .unwrap_or(config.crash_loop_window),
+                }),
+        };
+
+        if restart.backoff.is_nan() || restart.backoff < 1.0 {
+            bail!(
+                "Restart backoff must be at least 1, got {}",
+                restart.backoff
+            );
+        }
+        if !(0.0..=1.0).contains(&restart.jitter) {
+            bail!(
+                "Restart jitter must be between 0 and 1, got {}",
+                restart.jitter
+            );
+        }
+
+        Ok(restart)
+    }
+
+    fn prepare_command(config: &Config, idx: usize, spec: &CommandSpec) -> Result<Command> {
+        let cmd = &spec.command;
         let name = config.names.get(idx).unwrap();
         let idx_str = idx.to_string();
-        let hide = config.hide.contains(name) || config.hide.contains(&idx_str);
+        let hide = spec.hide || config.hide.contains(name) || config.hide.contains(&idx_str);
+        let restart = Self::restart(config, spec)
+            .with_context(|| format!("Invalid restart options for command '{}'", name))?;
+
+        let kill = KillSequence::new(
+            spec.kill_signal
+                .as_deref()
+                .or(config.kill_signal.as_deref()),
+            spec.kill_timeout
+                .map(Duration::from_millis)
+                .or(config.kill_timeout),
+        )
+        .with_context(|| format!("Invalid kill options for command '{}'", name))?;
 
-        let mut prefix = config
+        // Per-command kill options replace the global ones altogether, so that
+        // e.g. `kill-others-on-fail` on a command is not shadowed by a global
+        // `--kill-others`.
+        let (kill_others, kill_others_on_fail) = match (spec.kill_others, spec.kill_others_on_fail)
+        {
+            (None, None) => (config.kill_others, config.kill_others_on_fail),
+            (kill_others, kill_others_on_fail) => (
+                kill_others.unwrap_or_default(),
+                kill_others_on_fail.unwrap_or_default(),
+            ),
+        };
+
+        // Raw output would be written right across the terminal UI
+        let raw = spec.raw.unwrap_or(config.raw);
+        if raw && config.tui {
+            bail!("Command '{}' can't be raw when using --tui", name);
+        }
+        if raw && config.output_format == OutputFormat::Jsonl {
+            bail!(
+                "Command '{}' can't be raw when using --output-format jsonl",
+                name
+            );
+        }
+
+        let forward_signals = spec.forward_signals.unwrap_or_else(|| {
+            config
+                .signal_targets
+                .as_ref()
+                .is_none_or(|targets| targets.contains(&idx))
+        });
+
+        let mut prefix = spec
             .prefix
+            .as_ref()
+            .unwrap_or(&config.prefix)
             .replace("{index}", &format!("{}", idx))
-            .replace(
-                "{command}",
-                &Command::shorten(config.prefix_length, cmd.as_ref()),
-            )
+            .replace("{command}", &Command::shorten(config.prefix_length, cmd))
             .replace("{name}", name);
 
         if !config.no_color {
-            prefix = colorize(idx, config.prefix_colors.get(idx).unwrap(), &prefix)?;
+            let color = spec
+                .color
+                .as_ref()
+                .unwrap_or_else(|| config.prefix_colors.get(idx).unwrap());
+            prefix = colorize(idx, color, &prefix)?;
+        }
+
+        if let Some(cwd) = &spec.cwd {
+            if !cwd.is_dir() {
+                bail!(
+                    "Working directory {} of command '{}' is not a directory",
+                    cwd.display(),
+                    name
+                );
+            }
+        }
+
+        let depends_on = spec
+            .depends_on
+            .iter()
+            .map(|dependency| Dependency::parse(dependency, &config.names))
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("Invalid dependencies for command '{}'", name))?;
+
+        let readiness = Readiness::from_spec(spec, spec.cwd.as_deref())
+            .with_context(|| format!("Invalid readiness checks for command '{}'", name))?;
+        // The output of raw commands is not read, so the pattern could never match
+        if raw && readiness.as_ref().is_some_and(|r| r.log.is_some()) {
+            bail!("Command '{}' can't be raw when using ready-log", name);
         }
 
+        let watch_signal = match &spec.watch_signal {
+            Some(signal) => signal.parse()?,
+            None => config.watch_signal,
+        };
+        let watch_patterns = if spec.watch.is_empty() {
+            &config.watch
+        } else {
+            &spec.watch
+        };
+        let watch = Watch::new(watch_patterns, spec.cwd.as_deref(), watch_signal)
+            .with_context(|| format!("Invalid watch options for command '{}'", name))?;
+
         let command = Command {
             prefix,
             hide,
-            raw: config.raw,
-            timestamp_format: config.timestamp_format.clone(),
-            command: cmd.as_ref().to_string(),
+            raw,
+            handle_input: config.handle_input,
+            tui: config.tui,
+            pty: !raw && spec.pty.unwrap_or(config.pty),
+            timestamp_format: spec
+                .timestamp_format
+                .clone()
+                .unwrap_or_else(|| config.timestamp_format.clone()),
+            command: cmd.clone(),
             pid: Default::default(),
-            restart_tries: AtomicI32::new(config.restart_tries),
-            restart_indefinitely: config.restart_tries < 0,
+            restart,
+            kill,
+            forward_signals,
+            kill_others,
+            kill_others_on_fail,
+            cwd: spec.cwd.clone(),
+            depends_on,
+            readiness,
+            watch: watch.map(Arc::new),
         };
 
         Ok(command)
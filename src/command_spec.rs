@@ -0,0 +1,287 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_derive::Deserialize;
+
+/// A command to be run, along with its per-command settings. Settings that are
+/// not set fall back to their global values in [`crate::config::Config`].
+///
+/// Specs are either read from the config file (see [`crate::config_file`]) or
+/// parsed from the command line (see [`CommandSpec::parse`]).
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CommandSpec {
+    /// The full command to be executed, see [`crate::Command::command`]
+    pub command: String,
+
+    /// See [`crate::cli::Args::names`]
+    pub name: Option<String>,
+
+    /// See [`crate::cli::Args::prefix_colors`]
+    pub color: Option<String>,
+
+    /// See [`crate::cli::Args::prefix`]
+    pub prefix: Option<String>,
+
+    /// See [`crate::Command::hide`]
+    #[serde(default)]
+    pub hide: bool,
+
+    /// See [`crate::cli::Args::raw`]
+    pub raw: Option<bool>,
+
+    /// See [`crate::cli::Args::pty`]
+    pub pty: Option<bool>,
+
+    /// See [`crate::cli::Args::timestamp_format`]
+    pub timestamp_format: Option<String>,
+
+    /// See [`crate::cli::Args::kill_signal`]
+    pub kill_signal: Option<String>,
+
+    /// See [`crate::cli::Args::kill_timeout`]
+    pub kill_timeout: Option<u64>,
+
+    /// Whether signals received by `rly` are forwarded to the command,
+    /// see [`crate::cli::Args::signal_targets`]
+    pub forward_signals: Option<bool>,
+
+    /// See [`crate::cli::Args::restart_policy`]
+    pub restart_policy: Option<String>,
+
+    /// See [`crate::cli::Args::restart_tries`]
+    pub restart_tries: Option<i32>,
+
+    /// See [`crate::cli::Args::restart_after`]
+    pub restart_after: Option<u64>,
+
+    /// See [`crate::cli::Args::restart_backoff`]
+    pub restart_backoff: Option<f64>,
+
+    /// See [`crate::cli::Args::restart_max_delay`]
+    pub restart_max_delay: Option<u64>,
+
+    /// See [`crate::cli::Args::restart_jitter`]
+    pub restart_jitter: Option<f64>,
+
+    /// See [`crate::cli::Args::restart_reset_after`]
+    pub restart_reset_after: Option<u64>,
+
+    /// See [`crate::cli::Args::crash_loop_failures`]
+    pub crash_loop_failures: Option<usize>,
+
+    /// See [`crate::cli::Args::crash_loop_window`]
+    pub crash_loop_window: Option<u64>,
+
+    /// See [`crate::cli::Args::kill_others`]. If either this or
+    /// [`CommandSpec::kill_others_on_fail`] is set, the global kill
+    /// options do not apply to this command.
+    pub kill_others: Option<bool>,
+
+    /// See [`crate::cli::Args::kill_others_on_fail`]
+    pub kill_others_on_fail: Option<bool>,
+
+    /// See [`crate::Command::cwd`]
+    pub cwd: Option<PathBuf>,
+
+    /// Commands that have to be ready (or completed) before this command
+    /// is started, see [`crate::dependencies::Dependency::parse`]
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+
+    /// See [`crate::readiness::Readiness::log`]
+    pub ready_log: Option<String>,
+
+    /// See [`crate::readiness::Readiness::port`]
+    pub ready_port: Option<u16>,
+
+    /// See [`crate::readiness::Readiness::http`]
+    pub ready_http: Option<String>,
+
+    /// See [`crate::readiness::Readiness::file`]
+    pub ready_file: Option<PathBuf>,
+
+    /// See [`crate::readiness::Readiness::timeout`], in milliseconds
+    pub ready_timeout: Option<u64>,
+
+    /// See [`crate::cli::Args::watch`]
+    #[serde(default)]
+    pub watch: Vec<String>,
+
+    /// See [`crate::cli::Args::watch_signal`]
+    pub watch_signal: Option<String>,
+}
+
+impl CommandSpec {
+    /// Parses a command given on the command line. The command may start with
+    /// a block of options that only apply to this command, e.g.
+    ///
+    /// ```text
+    /// [name=api,restart-tries=-1,kill-others=false] cargo run
+    /// ```
+    ///
+    /// Options are named like their command line counterparts. Boolean options
+    /// may omit their value to enable them, and `depends-on` may be given
+    /// multiple times, as may `watch`. Commas, closing brackets and
+    /// backslashes in values have to be escaped with a backslash.
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut spec = CommandSpec::default();
+
+        let Some((options, command)) = split_options(input)? else {
+            spec.command = input.to_string();
+            return Ok(spec);
+        };
+
+        for (key, value) in options {
+            spec.set(&key, value)
+                .with_context(|| format!("Invalid option '{}' for command '{}'", key, command))?;
+        }
+
+        spec.command = command.to_string();
+        Ok(spec)
+    }
+
+    fn set(&mut self, key: &str, value: Option<String>) -> Result<()> {
+        match key.replace('_', "-").as_str() {
+            "name" => self.name = Some(required(value)?),
+            "color" => self.color = Some(required(value)?),
+            "prefix" => self.prefix = Some(required(value)?),
+            "hide" => self.hide = flag(value)?,
+            "raw" => self.raw = Some(flag(value)?),
+            "pty" => self.pty = Some(flag(value)?),
+            "timestamp-format" => self.timestamp_format = Some(required(value)?),
+            "restart-tries" => self.restart_tries = Some(parse(value)?),
+            "restart-after" => self.restart_after = Some(parse(value)?),
+            "kill-signal" => self.kill_signal = Some(required(value)?),
+            "kill-timeout" => self.kill_timeout = Some(parse(value)?),
+            "forward-signals" => self.forward_signals = Some(flag(value)?),
+            "restart-policy" => self.restart_policy = Some(required(value)?),
+            "restart-backoff" => self.restart_backoff = Some(parse(value)?),
+            "restart-max-delay" => self.restart_max_delay = Some(parse(value)?),
+            "restart-jitter" => self.restart_jitter = Some(parse(value)?),
+            "restart-reset-after" => self.restart_reset_after = Some(parse(value)?),
+            "crash-loop-failures" => self.crash_loop_failures = Some(parse(value)?),
+            "crash-loop-window" => self.crash_loop_window = Some(parse(value)?),
+            "kill-others" => self.kill_others = Some(flag(value)?),
+            "kill-others-on-fail" => self.kill_others_on_fail = Some(flag(value)?),
+            "cwd" => self.cwd = Some(PathBuf::from(required(value)?)),
+            "depends-on" => self.depends_on.push(required(value)?),
+            "ready-log" => self.ready_log = Some(required(value)?),
+            "ready-port" => self.ready_port = Some(parse(value)?),
+            "ready-http" => self.ready_http = Some(required(value)?),
+            "ready-file" => self.ready_file = Some(PathBuf::from(required(value)?)),
+            "ready-timeout" => self.ready_timeout = Some(parse(value)?),
+            "watch" => self.watch.push(required(value)?),
+            "watch-signal" => self.watch_signal = Some(required(value)?),
+            _ => bail!("Unknown option"),
+        }
+
+        Ok(())
+    }
+}
+
+type Options = Vec<(String, Option<String>)>;
+
+/// Splits a leading `[key=value,...]` block off the given input. Returns `None`
+/// if the input does not start with such a block, which is the case for most
+/// shell commands (including `[ -f some-file ]`, because of the whitespace).
+fn split_options(input: &str) -> Result<Option<(Options, &str)>> {
+    let Some(rest) = input.strip_prefix('[') else {
+        return Ok(None);
+    };
+
+    if !rest.starts_with(|c: char| c.is_ascii_lowercase()) {
+        return Ok(None);
+    }
+
+    let mut options = vec![];
+    let mut current = String::new();
+    let mut chars = rest.char_indices();
+    let end = loop {
+        match chars.next() {
+            Some((_, '\\')) => match chars.next() {
+                Some((_, c)) => current.push(c),
+                None => break None,
+            },
+            Some((_, ',')) => options.push(std::mem::take(&mut current)),
+            Some((idx, ']')) => {
+                options.push(std::mem::take(&mut current));
+                break Some(idx);
+            }
+            Some((_, c)) => current.push(c),
+            None => break None,
+        }
+    };
+
+    let end = end.ok_or_else(|| anyhow!("Unterminated option block in command '{}'", input))?;
+    let options = options
+        .into_iter()
+        .map(|option| match option.split_once('=') {
+            Some((key, value)) => (key.trim().to_string(), Some(value.to_string())),
+            None => (option.trim().to_string(), None),
+        })
+        .collect();
+
+    Ok(Some((options, rest[end + 1..].trim_start())))
+}
+
+fn required(value: Option<String>) -> Result<String> {
+    value.ok_or_else(|| anyhow!("A value is required"))
+}
+
+fn parse<T>(value: Option<String>) -> Result<T>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    Ok(required(value)?.trim().parse()?)
+}
+
+fn flag(value: Option<String>) -> Result<bool> {
+    match value {
+        None => Ok(true),
+        value => parse(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_commands() {
+        for cmd in ["cargo run", "[ -f some-file ] && cat some-file", "[[ 1 ]]"] {
+            assert_eq!(cmd, CommandSpec::parse(cmd).unwrap().command);
+        }
+    }
+
+    #[test]
+    fn test_inline_options() {
+        let spec = CommandSpec::parse(
+            "[name=api,restart-tries=-1,raw,kill-others=false,prefix={name}\\,{pid},depends-on=db,\
+             depends-on=migrate:completed] cargo run",
+        )
+        .unwrap();
+
+        let expected = CommandSpec {
+            command: "cargo run".to_string(),
+            name: Some("api".to_string()),
+            prefix: Some("{name},{pid}".to_string()),
+            raw: Some(true),
+            restart_tries: Some(-1),
+            kill_others: Some(false),
+            depends_on: vec!["db".to_string(), "migrate:completed".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(expected, spec);
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        assert!(CommandSpec::parse("[restart-tries=foo] ls").is_err());
+        assert!(CommandSpec::parse("[unknown] ls").is_err());
+        assert!(CommandSpec::parse("[name=foo ls").is_err());
+    }
+}
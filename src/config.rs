@@ -1,10 +1,32 @@
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 
+use anyhow::bail;
+
+use crate::colors::colorize;
+use crate::command_spec::CommandSpec;
+use crate::config_file::ConfigFile;
+use crate::control::default_socket_path;
+use crate::group::GroupOrder;
+use crate::output::OutputFormat;
+use crate::passthrough::substitute;
+use crate::restart::RestartPolicy;
+use crate::success::SuccessCondition;
+use crate::watch::WatchSignal;
 use crate::{Args, Command, Commands};
 
+const DEFAULT_PREFIX: &str = "[{name}]";
+const DEFAULT_PREFIX_COLORS: &str = "reset";
+const DEFAULT_STREAM_COLORS: &str = "reset";
+const DEFAULT_PREFIX_LENGTH: usize = 10;
+const DEFAULT_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S.%3f";
+const DEFAULT_SUCCESS: &str = "all";
+const DEFAULT_CRASH_LOOP_WINDOW: Duration = Duration::from_secs(30);
+
 #[derive(Debug)]
 pub struct Config {
-    pub commands: Vec<Command>,
+    pub commands: Vec<Arc<Command>>,
     pub names: Vec<String>,
     pub prefix_colors: Vec<String>,
     pub restart_after: Duration,
@@ -16,23 +38,108 @@ pub struct Config {
     /// See [`Args::raw`]
     pub raw: bool,
 
+    /// See [`Args::pty`]
+    pub pty: bool,
+
     /// See [`Args::prefix_length`]
     pub prefix_length: usize,
 
     /// See [`Args::no_color`]
     pub no_color: bool,
 
+    /// See [`Args::merge_stderr`]
+    pub merge_stderr: bool,
+
+    /// See [`Args::handle_input`]
+    pub handle_input: bool,
+
+    /// Index of the command referenced by [`Args::default_input_target`]
+    pub default_input_target: usize,
+
+    /// See [`Args::stream_colors`], with one entry for stdout and one for stderr
+    pub stream_colors: Vec<String>,
+
+    /// See [`Args::group`]
+    pub group: bool,
+
+    /// See [`Args::group_order`]
+    pub group_order: GroupOrder,
+
+    /// See [`Args::timings`]
+    pub timings: bool,
+
+    /// See [`Args::tui`]
+    pub tui: bool,
+
+    /// Where the socket of [`Args::control`] is created, if it is enabled
+    pub control_socket: Option<PathBuf>,
+
+    /// See [`Args::output_format`]
+    pub output_format: OutputFormat,
+
     /// See [`Args::timestamp_format`]
     pub timestamp_format: String,
 
+    /// See [`Args::kill_signal`]
+    pub kill_signal: Option<String>,
+
+    /// See [`Args::kill_timeout`]
+    pub kill_timeout: Option<Duration>,
+
+    /// Indices of the commands referenced by [`Args::signal_targets`],
+    /// or `None` if signals are forwarded to all of them
+    pub signal_targets: Option<Vec<usize>>,
+
+    /// See [`Args::init`]
+    pub init: bool,
+
+    /// Whether `rly` handles Ctrl-C, `SIGTERM` and the forwarded signals (see
+    /// [`Args::signal_targets`]). Always the case on the command line, but
+    /// only if enabled using [`crate::Runner::handle_signals`] for the library.
+    pub handle_signals: bool,
+
+    /// See [`Args::restart_policy`]. If not given, the default policy
+    /// only applies if [`Config::restart_tries`] is given.
+    pub restart_policy: Option<RestartPolicy>,
+
     /// See [`Args::restart_tries`]
-    pub restart_tries: i32,
+    pub restart_tries: Option<i32>,
+
+    /// See [`Args::restart_backoff`]
+    pub restart_backoff: f64,
+
+    /// See [`Args::restart_max_delay`]
+    pub restart_max_delay: Option<Duration>,
+
+    /// See [`Args::restart_jitter`]
+    pub restart_jitter: f64,
+
+    /// See [`Args::restart_reset_after`]
+    pub restart_reset_after: Option<Duration>,
+
+    /// See [`Args::crash_loop_failures`]
+    pub crash_loop_failures: Option<usize>,
+
+    /// See [`Args::crash_loop_window`]
+    pub crash_loop_window: Duration,
+
+    /// See [`Args::kill_others_on_crash_loop`]
+    pub kill_others_on_crash_loop: bool,
 
     /// See [`Args::kill_others`]
     pub kill_others: bool,
 
     /// See [`Args::kill_others_on_fail`]
     pub kill_others_on_fail: bool,
+
+    /// See [`Args::success`]
+    pub success: SuccessCondition,
+
+    /// See [`Args::watch`]
+    pub watch: Vec<String>,
+
+    /// See [`Args::watch_signal`]
+    pub watch_signal: WatchSignal,
 }
 
 impl Config {
@@ -41,6 +148,18 @@ impl Config {
     }
 }
 
+/// Finds the index of the command referenced either by its name or by its index
+pub fn find_command(names: &[String], reference: &str) -> Option<usize> {
+    if let Some(idx) = names.iter().position(|name| name == reference) {
+        return Some(idx);
+    }
+
+    match reference.parse::<usize>() {
+        Ok(idx) if idx < names.len() => Some(idx),
+        _ => None,
+    }
+}
+
 fn maybe_repeat(input: &str, separator: char, count: usize) -> Vec<String> {
     let mut result: Vec<_> = input.split(separator).map(|s| s.to_string()).collect();
 
@@ -52,40 +171,349 @@ fn maybe_repeat(input: &str, separator: char, count: usize) -> Vec<String> {
     result
 }
 
+/// A flag given on the command line, which overrides the config file. Flags
+/// enabled in the file are disabled by their `--no-` counterpart.
+fn flag(enabled: bool, disabled: bool, file: Option<bool>) -> bool {
+    enabled || (!disabled && file.unwrap_or_default())
+}
+
+fn override_with<T: Clone>(target: &mut Option<T>, value: &Option<T>) {
+    if value.is_some() {
+        target.clone_from(value);
+    }
+}
+
 impl TryFrom<Args> for Config {
     type Error = anyhow::Error;
 
+    /// Builds the [`Config`] from the given [`Args`], merging in the options
+    /// from the config file (see [`Args::config`]). Options given on the
+    /// command line take precedence over those from the file.
     fn try_from(args: Args) -> Result<Self, Self::Error> {
-        let names: Vec<_> = match &args.names {
-            None => (0..args.commands.len()).map(|i| i.to_string()).collect(),
-            Some(s) => maybe_repeat(s, args.name_separator, args.commands.len()),
+        let file = ConfigFile::discover(args.config.as_deref())?.unwrap_or_default();
+        Config::new(args, file)
+    }
+}
+
+impl Config {
+    /// Builds the [`Config`] from the given [`Args`] and config file, like
+    /// [`Config::try_from`] does for the file it discovers
+    pub fn new(mut args: Args, file: ConfigFile) -> anyhow::Result<Self> {
+        let passthrough_arguments = flag(
+            args.passthrough_arguments,
+            args.no_passthrough_arguments,
+            file.passthrough_arguments,
+        );
+        let additional_arguments = std::mem::take(&mut args.additional_arguments);
+        if !passthrough_arguments {
+            args.commands.extend(additional_arguments.iter().cloned());
+        }
+
+        // Commands given on the command line replace those from the config file.
+        // Options given on the command line override the per-command options from
+        // the file, but not the inline options of commands given on the command
+        // line (see [`CommandSpec::parse`]), which are more specific.
+        let from_file = args.commands.is_empty();
+        let mut specs: Vec<CommandSpec> = if from_file {
+            file.commands
+        } else {
+            args.commands
+                .iter()
+                .map(|cmd| CommandSpec::parse(cmd))
+                .collect::<Result<_, _>>()?
         };
 
-        let prefix_colors: Vec<_> = maybe_repeat(&args.prefix_colors, ',', args.commands.len());
-        let restart_after = Duration::from_millis(args.restart_after);
+        if passthrough_arguments {
+            for spec in specs.iter_mut() {
+                spec.command = substitute(&spec.command, &additional_arguments);
+            }
+        }
+
+        if let Some(names) = &args.names {
+            let names = maybe_repeat(names, args.name_separator, specs.len());
+            for (spec, name) in specs.iter_mut().zip(names) {
+                if from_file || spec.name.is_none() {
+                    spec.name = Some(name);
+                }
+            }
+        }
+
+        if let Some(colors) = &args.prefix_colors {
+            let colors = maybe_repeat(colors, ',', specs.len());
+            for (spec, color) in specs.iter_mut().zip(colors) {
+                if from_file || spec.color.is_none() {
+                    spec.color = Some(color);
+                }
+            }
+        }
+
+        if from_file {
+            for spec in specs.iter_mut() {
+                override_with(&mut spec.prefix, &args.prefix);
+                override_with(&mut spec.timestamp_format, &args.timestamp_format);
+                override_with(&mut spec.restart_tries, &args.restart_tries);
+                override_with(&mut spec.restart_after, &args.restart_after);
+                override_with(&mut spec.restart_policy, &args.restart_policy);
+                override_with(&mut spec.kill_signal, &args.kill_signal);
+                override_with(&mut spec.kill_timeout, &args.kill_timeout);
+                override_with(&mut spec.restart_backoff, &args.restart_backoff);
+                override_with(&mut spec.restart_max_delay, &args.restart_max_delay);
+                override_with(&mut spec.restart_jitter, &args.restart_jitter);
+                override_with(&mut spec.restart_reset_after, &args.restart_reset_after);
+                override_with(&mut spec.crash_loop_failures, &args.crash_loop_failures);
+                override_with(&mut spec.crash_loop_window, &args.crash_loop_window);
+                override_with(&mut spec.watch_signal, &args.watch_signal);
+
+                if !args.watch.is_empty() {
+                    spec.watch.clone_from(&args.watch);
+                }
+
+                if args.raw || args.no_raw {
+                    spec.raw = Some(args.raw);
+                }
+
+                if args.pty || args.no_pty {
+                    spec.pty = Some(args.pty);
+                }
+
+                if args.kill_others || args.kill_others_on_fail {
+                    spec.kill_others = Some(args.kill_others);
+                    spec.kill_others_on_fail = Some(args.kill_others_on_fail);
+                }
+                if args.no_kill_others {
+                    spec.kill_others = spec.kill_others.map(|_| false);
+                }
+                if args.no_kill_others_on_fail {
+                    spec.kill_others_on_fail = spec.kill_others_on_fail.map(|_| false);
+                }
+            }
+        }
+
+        let names: Vec<_> = specs
+            .iter()
+            .enumerate()
+            .map(|(idx, spec)| spec.name.clone().unwrap_or_else(|| idx.to_string()))
+            .collect();
+
+        let prefix_colors = maybe_repeat(
+            file.prefix_colors
+                .as_deref()
+                .unwrap_or(DEFAULT_PREFIX_COLORS),
+            ',',
+            specs.len(),
+        );
+
+        let restart_after = Duration::from_millis(
+            args.restart_after
+                .or(file.restart_after)
+                .unwrap_or_default(),
+        );
+
+        let restart_policy = args
+            .restart_policy
+            .as_deref()
+            .or(file.restart_policy.as_deref())
+            .map(str::parse)
+            .transpose()?;
+
+        let restart_max_delay = args
+            .restart_max_delay
+            .or(file.restart_max_delay)
+            .map(Duration::from_millis);
+
+        let restart_reset_after = args
+            .restart_reset_after
+            .or(file.restart_reset_after)
+            .map(Duration::from_millis);
+
+        let success = SuccessCondition::parse(
+            args.success
+                .as_deref()
+                .or(file.success.as_deref())
+                .unwrap_or(DEFAULT_SUCCESS),
+            &names,
+        )?;
+
+        let watch_signal = args
+            .watch_signal
+            .as_deref()
+            .or(file.watch_signal.as_deref())
+            .map(str::parse)
+            .transpose()?
+            .unwrap_or_default();
+
+        let watch = if args.watch.is_empty() {
+            file.watch.unwrap_or_default()
+        } else {
+            args.watch
+        };
+
+        let group_order = args
+            .group_order
+            .as_deref()
+            .or(file.group_order.as_deref())
+            .map(str::parse)
+            .transpose()?
+            .unwrap_or_default();
+
+        let output_format = args
+            .output_format
+            .as_deref()
+            .or(file.output_format.as_deref())
+            .map(str::parse)
+            .transpose()?
+            .unwrap_or_default();
+
+        let stream_colors = maybe_repeat(
+            args.stream_colors
+                .as_deref()
+                .or(file.stream_colors.as_deref())
+                .unwrap_or(DEFAULT_STREAM_COLORS),
+            ',',
+            2,
+        );
+        if stream_colors.len() > 2 {
+            bail!(
+                "Expected at most two stream colors (stdout and stderr), got {}",
+                stream_colors.len()
+            );
+        }
+        for color in &stream_colors {
+            colorize(0, color, "")?;
+        }
+
+        let default_input_target = match args
+            .default_input_target
+            .as_deref()
+            .or(file.default_input_target.as_deref())
+        {
+            Some(target) => match find_command(&names, target) {
+                Some(idx) => idx,
+                None => bail!("Unknown default input target '{}'", target),
+            },
+            None => 0,
+        };
+
+        let signal_targets = args
+            .signal_targets
+            .as_deref()
+            .or(file.signal_targets.as_deref())
+            .map(|targets| {
+                targets
+                    .split(',')
+                    .map(|target| match find_command(&names, target) {
+                        Some(idx) => Ok(idx),
+                        None => bail!("Unknown signal target '{}'", target),
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()?;
 
         let hide = args
             .hide
             .map(|x| x.split(',').map(|s| s.to_string()).collect())
             .unwrap_or_default();
 
+        let kill_others = flag(args.kill_others, args.no_kill_others, file.kill_others);
+        let kill_others_on_fail = flag(
+            args.kill_others_on_fail,
+            args.no_kill_others_on_fail,
+            file.kill_others_on_fail,
+        );
+        if kill_others && kill_others_on_fail {
+            bail!(
+                "\nYou supplied both --kill-others and --kill-others-on-fail,\
+                 \nbut the the latter is implied by the former. Please only \
+                 \nprovide one of these options"
+            )
+        }
+
+        let tui = flag(args.tui, args.no_tui, file.tui);
+        let raw = flag(args.raw, args.no_raw, file.raw);
+        let handle_input = flag(args.handle_input, args.no_handle_input, file.handle_input);
+        if tui && (raw || handle_input) {
+            bail!("--tui can't be combined with --raw or --handle-input")
+        }
+        // The output of raw commands is not piped through rly, so it can't be turned into JSON
+        if raw && output_format == OutputFormat::Jsonl {
+            bail!("--raw can't be combined with --output-format jsonl")
+        }
+
+        let control = flag(args.control, args.no_control, file.control);
+        let control_socket = match args.control_socket.or(file.control_socket) {
+            // Also disables a socket configured in the file
+            _ if args.no_control => None,
+            Some(path) => Some(path),
+            None => control.then(default_socket_path),
+        };
+
         let mut config = Config {
             commands: vec![],
             names,
             hide,
             prefix_colors,
             restart_after,
-            prefix: args.prefix,
-            raw: args.raw,
-            prefix_length: args.prefix_length,
-            no_color: args.no_color,
-            timestamp_format: args.timestamp_format,
-            restart_tries: args.restart_tries,
-            kill_others: args.kill_others,
-            kill_others_on_fail: args.kill_others_on_fail,
+            prefix: args
+                .prefix
+                .or(file.prefix)
+                .unwrap_or_else(|| DEFAULT_PREFIX.to_string()),
+            raw,
+            pty: flag(args.pty, args.no_pty, file.pty),
+            prefix_length: args
+                .prefix_length
+                .or(file.prefix_length)
+                .unwrap_or(DEFAULT_PREFIX_LENGTH),
+            no_color: flag(args.no_color, args.color, file.no_color),
+            merge_stderr: flag(args.merge_stderr, args.no_merge_stderr, file.merge_stderr),
+            handle_input,
+            default_input_target,
+            stream_colors,
+            group: flag(args.group, args.no_group, file.group),
+            group_order,
+            timings: flag(args.timings, args.no_timings, file.timings),
+            tui,
+            control_socket,
+            output_format,
+            timestamp_format: args
+                .timestamp_format
+                .or(file.timestamp_format)
+                .unwrap_or_else(|| DEFAULT_TIMESTAMP_FORMAT.to_string()),
+            kill_signal: args.kill_signal.or(file.kill_signal),
+            kill_timeout: args
+                .kill_timeout
+                .or(file.kill_timeout)
+                .map(Duration::from_millis),
+            signal_targets,
+            init: flag(args.init, args.no_init, file.init),
+            handle_signals: true,
+            restart_policy,
+            restart_tries: args.restart_tries.or(file.restart_tries),
+            restart_backoff: args.restart_backoff.or(file.restart_backoff).unwrap_or(1.0),
+            restart_max_delay,
+            restart_jitter: args
+                .restart_jitter
+                .or(file.restart_jitter)
+                .unwrap_or_default(),
+            restart_reset_after,
+            crash_loop_failures: args.crash_loop_failures.or(file.crash_loop_failures),
+            crash_loop_window: args
+                .crash_loop_window
+                .or(file.crash_loop_window)
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_CRASH_LOOP_WINDOW),
+            kill_others_on_crash_loop: flag(
+                args.kill_others_on_crash_loop,
+                args.no_kill_others_on_crash_loop,
+                file.kill_others_on_crash_loop,
+            ),
+            kill_others,
+            kill_others_on_fail,
+            success,
+            watch,
+            watch_signal,
         };
 
-        config.commands = Commands::from(&config, args.commands.as_slice())?;
+        config.commands = Commands::from(&config, specs.as_slice())?;
         Ok(config)
     }
 }
@@ -0,0 +1,156 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde_derive::Deserialize;
+
+use crate::command_spec::CommandSpec;
+
+/// Name of the config file that is picked up automatically
+/// if it exists in the current working directory.
+pub const DEFAULT_CONFIG_FILE: &str = "rly.toml";
+
+/// Contents of a config file, e.g.
+///
+/// ```toml
+/// kill_others = true
+///
+/// [[commands]]
+/// name = "api"
+/// command = "cargo run"
+/// color = "blue"
+/// restart_tries = -1
+/// cwd = "backend"
+/// ```
+///
+/// All global options are optional and mirror the options of
+/// [`crate::cli::Args`]. Options given on the command line take
+/// precedence over those from the file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    pub prefix: Option<String>,
+    pub prefix_colors: Option<String>,
+    pub prefix_length: Option<usize>,
+    pub timestamp_format: Option<String>,
+    pub raw: Option<bool>,
+    pub pty: Option<bool>,
+    pub no_color: Option<bool>,
+    pub merge_stderr: Option<bool>,
+    pub handle_input: Option<bool>,
+    pub default_input_target: Option<String>,
+    pub stream_colors: Option<String>,
+    pub group: Option<bool>,
+    pub group_order: Option<String>,
+    pub timings: Option<bool>,
+    pub tui: Option<bool>,
+    pub control: Option<bool>,
+    pub control_socket: Option<PathBuf>,
+    pub output_format: Option<String>,
+    pub passthrough_arguments: Option<bool>,
+    pub kill_others: Option<bool>,
+    pub kill_others_on_fail: Option<bool>,
+    pub kill_signal: Option<String>,
+    pub kill_timeout: Option<u64>,
+    pub signal_targets: Option<String>,
+    pub init: Option<bool>,
+    pub restart_tries: Option<i32>,
+    pub restart_after: Option<u64>,
+    pub restart_policy: Option<String>,
+    pub restart_backoff: Option<f64>,
+    pub restart_max_delay: Option<u64>,
+    pub restart_jitter: Option<f64>,
+    pub restart_reset_after: Option<u64>,
+    pub crash_loop_failures: Option<usize>,
+    pub crash_loop_window: Option<u64>,
+    pub kill_others_on_crash_loop: Option<bool>,
+    pub success: Option<String>,
+    pub watch: Option<Vec<String>>,
+    pub watch_signal: Option<String>,
+
+    #[serde(default)]
+    pub commands: Vec<CommandSpec>,
+}
+
+impl ConfigFile {
+    /// Loads the config file at `path`, or `rly.toml` in the current working
+    /// directory if no path is given. Returns `None` if no path was given and
+    /// there is no `rly.toml`.
+    pub fn discover(path: Option<&Path>) -> Result<Option<Self>> {
+        match path {
+            Some(path) => Self::load(path).map(Some),
+            None => {
+                let path = Path::new(DEFAULT_CONFIG_FILE);
+                if path.is_file() {
+                    Self::load(path).map(Some)
+                } else {
+                    Ok(None)
+                }
+            }
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+
+        let mut file: ConfigFile = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+
+        // Working directories are relative to the location of the config file,
+        // not to wherever rly happens to be invoked from.
+        let base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        for spec in file.commands.iter_mut() {
+            spec.cwd = spec.cwd.take().map(|cwd| resolve(&base_dir, cwd));
+        }
+
+        Ok(file)
+    }
+}
+
+fn resolve(base_dir: &Path, path: PathBuf) -> PathBuf {
+    if path.is_absolute() {
+        path
+    } else {
+        base_dir.join(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let file: ConfigFile = toml::from_str(
+            r#"
+            prefix = "{name}:"
+            kill_others = true
+
+            [[commands]]
+            command = "cargo run"
+            name = "api"
+            restart_tries = -1
+
+            [[commands]]
+            command = "npm start"
+            hide = true
+            cwd = "frontend"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(Some("{name}:".to_string()), file.prefix);
+        assert_eq!(Some(true), file.kill_others);
+        assert_eq!(2, file.commands.len());
+        assert_eq!(Some("api".to_string()), file.commands[0].name);
+        assert_eq!(Some(-1), file.commands[0].restart_tries);
+        assert!(file.commands[1].hide);
+        assert_eq!(Some(PathBuf::from("frontend")), file.commands[1].cwd);
+    }
+
+    #[test]
+    fn test_rejects_unknown_fields() {
+        let res = toml::from_str::<ConfigFile>("[[commands]]\ncommand = \"ls\"\ncolour = \"red\"");
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,449 @@
+use std::collections::VecDeque;
+use std::fs::DirBuilder;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use chrono::{Local, SecondsFormat};
+use log::debug;
+use serde::Serialize;
+use serde_derive::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::{AbortHandle, JoinSet};
+
+use crate::output::Stream;
+
+/// How many lines of output are kept for `logs` requests, for
+/// each command and for all of them
+const HISTORY: usize = 1000;
+
+/// How many lines may be pending for a client following the output,
+/// before it is considered too slow and disconnected
+const FOLLOW_BUFFER: usize = 1024;
+
+/// Where the control socket is created if [`crate::cli::Args::control_socket`]
+/// is not given: in `$XDG_RUNTIME_DIR`, or in a directory of the temporary
+/// directory with the user id in its name if that is not set. Either one
+/// has to be accessible only by the current user.
+pub fn default_socket_path() -> PathBuf {
+    let dir = match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => std::env::temp_dir().join(format!("rly-{}", nix::unistd::getuid())),
+    };
+    dir.join("rly.sock")
+}
+
+/// A request sent to the control socket, as a JSON object on a line of its
+/// own, e.g. `{"command": "restart", "target": "api"}`. Targets are commands
+/// given by name or index. Requests without a target apply to all commands.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "lowercase")]
+pub enum Request {
+    /// Lists all commands along with their status, see [`CommandInfo`]
+    List,
+
+    Start {
+        target: Option<String>,
+    },
+
+    Stop {
+        target: Option<String>,
+    },
+
+    Restart {
+        target: Option<String>,
+    },
+
+    /// Sends a signal (e.g. `SIGHUP` or `hup`) to the process group of a command
+    Signal {
+        target: Option<String>,
+        signal: String,
+    },
+
+    /// Sends the most recent output (at most `lines` of it), followed by all
+    /// new output if `follow` is set. The connection only carries
+    /// [`LogLine`]s after this request.
+    Logs {
+        target: Option<String>,
+        lines: Option<usize>,
+        #[serde(default)]
+        follow: bool,
+    },
+}
+
+/// The answer to every [`Request`], as a JSON object on a line of its own
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    /// Answers a [`Request::List`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commands: Option<Vec<CommandInfo>>,
+}
+
+impl Response {
+    pub fn ok() -> Self {
+        Response {
+            ok: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn error(error: &anyhow::Error) -> Self {
+        Response {
+            ok: false,
+            error: Some(format!("{:#}", error)),
+            ..Default::default()
+        }
+    }
+}
+
+/// A command, as listed by [`Request::List`]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommandInfo {
+    pub index: usize,
+    pub name: String,
+    pub command: String,
+
+    /// One of `waiting`, `starting`, `restarting`, `running`, `ready`,
+    /// `stopping`, `exited` or `skipped`
+    pub status: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uptime_ms: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+
+    pub restarts: u32,
+}
+
+/// A line of output, as sent after a [`Request::Logs`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogLine {
+    pub timestamp: String,
+    pub index: usize,
+    pub name: String,
+    pub stream: Stream,
+    pub line: String,
+}
+
+/// What the event loop replies to a [`Request`] with
+pub enum Reply {
+    Response(Response),
+
+    /// Answers a [`Request::Logs`]
+    Logs {
+        history: Vec<LogLine>,
+        follow: Option<mpsc::Receiver<LogLine>>,
+    },
+}
+
+/// A request along with the channel its reply is sent back on
+pub type PendingRequest = (Request, oneshot::Sender<Reply>);
+
+/// The control socket, see [`crate::cli::Args::control`]. Requests are carried
+/// out by the event loop, which keeps the output around for `logs` requests
+/// using [`Control::record`]. The socket is removed once this is dropped.
+pub struct Control {
+    path: PathBuf,
+
+    /// The task accepting connections, which owns the tasks handling them
+    server: AbortHandle,
+
+    /// Recent output of each command
+    history: Vec<VecDeque<LogLine>>,
+
+    /// Recent output of all commands
+    all: VecDeque<LogLine>,
+
+    /// Clients following the output of a command (or of all of them)
+    followers: Vec<(Option<usize>, mpsc::Sender<LogLine>)>,
+}
+
+impl Control {
+    /// Starts listening on the given path. Requests are passed on to
+    /// the returned channel, along with a channel for the reply.
+    pub fn listen(path: &Path, commands: usize) -> Result<(Self, mpsc::Receiver<PendingRequest>)> {
+        let is_default = path == default_socket_path();
+        if is_default {
+            if let Some(dir) = path.parent() {
+                private_dir(dir)?;
+            }
+        }
+
+        // Sockets are not removed if rly is killed, but
+        // they may belong to an instance that is running
+        if let Ok(metadata) = std::fs::symlink_metadata(path) {
+            if metadata.uid() != nix::unistd::getuid().as_raw() {
+                bail!("{} belongs to another user", path.display());
+            }
+            if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                let hint = if is_default {
+                    ", use --control-socket to run another one"
+                } else {
+                    ""
+                };
+                bail!(
+                    "Another instance of rly is listening on {}{hint}",
+                    path.display()
+                );
+            }
+            std::fs::remove_file(path)
+                .with_context(|| format!("Unable to remove stale socket {}", path.display()))?;
+        }
+
+        let listener = bind_private(path)
+            .with_context(|| format!("Unable to listen on {}", path.display()))?;
+
+        let (tx, rx) = mpsc::channel(16);
+        let server = tokio::spawn(async move {
+            let mut connections = JoinSet::new();
+            loop {
+                tokio::select! {
+                    accepted = listener.accept() => match accepted {
+                        Ok((stream, _)) => {
+                            connections.spawn(handle_connection(stream, tx.clone()));
+                        }
+                        Err(err) => debug!("Failed to accept control connection: {err}"),
+                    },
+                    Some(result) = connections.join_next() => {
+                        if let Ok(Err(err)) = result {
+                            debug!("Control connection failed: {:?}", err);
+                        }
+                    }
+                }
+            }
+        })
+        .abort_handle();
+
+        let control = Control {
+            path: path.to_path_buf(),
+            server,
+            history: vec![VecDeque::new(); commands],
+            all: VecDeque::new(),
+            followers: vec![],
+        };
+        Ok((control, rx))
+    }
+
+    /// Keeps a line of output for `logs` requests, and sends it to the
+    /// clients following it
+    pub fn record(&mut self, command_idx: usize, name: &str, stream: Stream, line: &str) {
+        let line = LogLine {
+            timestamp: Local::now().to_rfc3339_opts(SecondsFormat::Millis, false),
+            index: command_idx,
+            name: name.to_string(),
+            stream,
+            line: line.to_string(),
+        };
+
+        // Clients that disconnected (or can't keep up) are dropped
+        self.followers.retain(|(target, tx)| {
+            if target.is_some_and(|target| target != command_idx) {
+                return !tx.is_closed();
+            }
+            tx.try_send(line.clone()).is_ok()
+        });
+
+        push_bounded(&mut self.history[command_idx], line.clone());
+        push_bounded(&mut self.all, line);
+    }
+
+    /// Answers a [`Request::Logs`] for the given command, or for all of them
+    pub fn logs(&mut self, target: Option<usize>, lines: Option<usize>, follow: bool) -> Reply {
+        let history = match target {
+            Some(idx) => &self.history[idx],
+            None => &self.all,
+        };
+        let skip = history.len().saturating_sub(lines.unwrap_or(HISTORY));
+        let history = history.iter().skip(skip).cloned().collect();
+
+        let follow = follow.then(|| {
+            let (tx, rx) = mpsc::channel(FOLLOW_BUFFER);
+            self.followers.push((target, tx));
+            rx
+        });
+
+        Reply::Logs { history, follow }
+    }
+}
+
+impl Drop for Control {
+    fn drop(&mut self) {
+        self.server.abort();
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            debug!("Failed to remove {}: {err}", self.path.display());
+        }
+    }
+}
+
+/// Creates the given directory accessible only by the current user, or makes
+/// sure that it is if it exists already, so that nobody else can tamper
+/// with the sockets in it
+fn private_dir(dir: &Path) -> Result<()> {
+    match DirBuilder::new().mode(0o700).create(dir) {
+        Err(err) if err.kind() != std::io::ErrorKind::AlreadyExists => {
+            return Err(err).with_context(|| format!("Unable to create {}", dir.display()));
+        }
+        _ => {}
+    }
+
+    let metadata = std::fs::symlink_metadata(dir)
+        .with_context(|| format!("Unable to access {}", dir.display()))?;
+    if !metadata.is_dir()
+        || metadata.uid() != nix::unistd::getuid().as_raw()
+        || metadata.mode() & 0o077 != 0
+    {
+        bail!(
+            "{} has to be a directory accessible only by the current user",
+            dir.display()
+        );
+    }
+    Ok(())
+}
+
+/// Binds a socket at the given path, without it ever being accessible by
+/// other users: it is bound in a private directory next to the path first,
+/// restricted, and only then linked to the path. Unlike renaming it, linking
+/// fails if another instance created the path in the meantime.
+fn bind_private(path: &Path) -> Result<UnixListener> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    // Temporary directories are only accessible by the current user
+    let dir = tempfile::Builder::new()
+        .prefix(".rly-")
+        .tempdir_in(parent)?;
+    let bound = dir.path().join("rly.sock");
+
+    let listener = UnixListener::bind(&bound)?;
+    std::fs::set_permissions(&bound, std::fs::Permissions::from_mode(0o600))?;
+    std::fs::hard_link(&bound, path)?;
+    Ok(listener)
+}
+
+fn push_bounded(lines: &mut VecDeque<LogLine>, line: LogLine) {
+    if lines.len() == HISTORY {
+        lines.pop_front();
+    }
+    lines.push_back(line);
+}
+
+/// Reads requests from a client until it disconnects, and writes the replies
+async fn handle_connection(
+    stream: UnixStream,
+    requests: mpsc::Sender<PendingRequest>,
+) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let reply = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                let (tx, rx) = oneshot::channel();
+                requests
+                    .send((request, tx))
+                    .await
+                    .context("rly is shutting down")?;
+                rx.await.context("rly is shutting down")?
+            }
+            Err(err) => Reply::Response(Response::error(
+                &anyhow::Error::new(err).context("Invalid request"),
+            )),
+        };
+
+        match reply {
+            Reply::Response(response) => write_json(&mut writer, &response).await?,
+            Reply::Logs { history, follow } => {
+                write_json(&mut writer, &Response::ok()).await?;
+                for line in history {
+                    write_json(&mut writer, &line).await?;
+                }
+                if let Some(mut follow) = follow {
+                    while let Some(line) = follow.recv().await {
+                        write_json(&mut writer, &line).await?;
+                    }
+                }
+                return Ok(());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn write_json<W: AsyncWrite + Unpin>(writer: &mut W, value: &impl Serialize) -> Result<()> {
+    let mut json = serde_json::to_string(value)?;
+    json.push('\n');
+    writer.write_all(json.as_bytes()).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        assert_eq!(
+            Request::List,
+            serde_json::from_str(r#"{"command": "list"}"#).unwrap()
+        );
+        assert_eq!(
+            Request::Restart {
+                target: Some("api".to_string())
+            },
+            serde_json::from_str(r#"{"command": "restart", "target": "api"}"#).unwrap()
+        );
+        assert_eq!(
+            Request::Logs {
+                target: None,
+                lines: Some(10),
+                follow: false
+            },
+            serde_json::from_str(r#"{"command": "logs", "lines": 10}"#).unwrap()
+        );
+        assert!(serde_json::from_str::<Request>(r#"{"command": "signal"}"#).is_err());
+        assert!(serde_json::from_str::<Request>(r#"{"command": "reboot"}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bind_private() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rly.sock");
+
+        let _listener = bind_private(&path).unwrap();
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(0o600, metadata.mode() & 0o777);
+        assert_eq!(1, std::fs::read_dir(dir.path()).unwrap().count());
+        std::os::unix::net::UnixStream::connect(&path).unwrap();
+
+        assert!(bind_private(&path).is_err());
+    }
+
+    #[test]
+    fn test_private_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let private = dir.path().join("rly");
+        private_dir(&private).unwrap();
+        assert_eq!(0o700, std::fs::metadata(&private).unwrap().mode() & 0o777);
+        private_dir(&private).unwrap();
+
+        std::fs::set_permissions(&private, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(private_dir(&private).is_err());
+    }
+}
@@ -0,0 +1,167 @@
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::UnixStream;
+
+use crate::cli::{CtlArgs, CtlCommand, Targets};
+use crate::control::{default_socket_path, CommandInfo, LogLine, Request, Response};
+use crate::output::Stream;
+use crate::tui::format_uptime;
+
+/// Runs `rly ctl`, see [`crate::cli::SubCommand::Ctl`]
+pub async fn run(args: CtlArgs) -> Result<ExitCode> {
+    let path = args.socket.unwrap_or_else(default_socket_path);
+    let mut client = Client::connect(&path).await?;
+
+    match args.command {
+        CtlCommand::Status => {
+            let response = client.request(&Request::List).await?;
+            print_status(&response.commands.unwrap_or_default());
+        }
+        CtlCommand::Start(targets) => {
+            for target in targets.into_requests() {
+                client.request(&Request::Start { target }).await?;
+            }
+        }
+        CtlCommand::Stop(targets) => {
+            for target in targets.into_requests() {
+                client.request(&Request::Stop { target }).await?;
+            }
+        }
+        CtlCommand::Restart(targets) => {
+            for target in targets.into_requests() {
+                client.request(&Request::Restart { target }).await?;
+            }
+        }
+        CtlCommand::Signal { signal, targets } => {
+            for target in targets.into_requests() {
+                let signal = signal.clone();
+                client.request(&Request::Signal { target, signal }).await?;
+            }
+        }
+        CtlCommand::Logs {
+            target,
+            follow,
+            lines,
+        } => {
+            // The prefix is only needed to tell the commands apart
+            let prefixed = target.is_none();
+            client
+                .request(&Request::Logs {
+                    target,
+                    lines,
+                    follow,
+                })
+                .await?;
+            while let Some(line) = client.lines.next_line().await? {
+                let line: LogLine = serde_json::from_str(&line).context("Invalid log line")?;
+                let text = match prefixed {
+                    true => format!("[{}] {}", line.name, line.line),
+                    false => line.line,
+                };
+                match line.stream {
+                    Stream::Stdout => println!("{}", text),
+                    Stream::Stderr => eprintln!("{}", text),
+                }
+            }
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+impl Targets {
+    /// The targets of the requests to send, where `None` means all commands
+    fn into_requests(self) -> Vec<Option<String>> {
+        match self.all {
+            true => vec![None],
+            false => self.targets.into_iter().map(Some).collect(),
+        }
+    }
+}
+
+/// A connection to the control socket of a running instance of `rly`
+struct Client {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+}
+
+impl Client {
+    async fn connect(path: &Path) -> Result<Self> {
+        let stream = UnixStream::connect(path).await.with_context(|| {
+            format!(
+                "Unable to connect to {}. Is rly running with --control?",
+                path.display()
+            )
+        })?;
+        let (reader, writer) = stream.into_split();
+
+        Ok(Client {
+            lines: BufReader::new(reader).lines(),
+            writer,
+        })
+    }
+
+    /// Sends a request and waits for its response, which is turned into an error if it failed
+    async fn request(&mut self, request: &Request) -> Result<Response> {
+        let mut json = serde_json::to_string(request)?;
+        json.push('\n');
+        self.writer.write_all(json.as_bytes()).await?;
+
+        let line = self
+            .lines
+            .next_line()
+            .await?
+            .context("rly closed the connection")?;
+        let response: Response = serde_json::from_str(&line).context("Invalid response")?;
+        if !response.ok {
+            return Err(anyhow!(response.error.unwrap_or_default()));
+        }
+
+        Ok(response)
+    }
+}
+
+fn print_status(commands: &[CommandInfo]) {
+    let rows: Vec<[String; 5]> = commands
+        .iter()
+        .map(|command| {
+            let status = match command.exit_code {
+                Some(code) => format!("{} ({})", command.status, code),
+                None => command.status.clone(),
+            };
+            let pid = command.pid.map(|pid| pid.to_string());
+            let uptime = command
+                .uptime_ms
+                .map(|uptime| format_uptime(Duration::from_millis(uptime)));
+            [
+                command.name.clone(),
+                status,
+                pid.unwrap_or_else(|| "-".to_string()),
+                uptime.unwrap_or_else(|| "-".to_string()),
+                command.restarts.to_string(),
+            ]
+        })
+        .collect();
+
+    let header = ["NAME", "STATUS", "PID", "UPTIME", "RESTARTS"].map(String::from);
+    let mut widths = header.clone().map(|column| column.len());
+    for row in &rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.chars().count());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(&rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(column, width)| format!("{:width$}", column, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
@@ -0,0 +1,166 @@
+use anyhow::{anyhow, bail, Result};
+
+use crate::config::find_command;
+
+/// When a dependency is considered to be satisfied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DependencyCondition {
+    /// The dependency has been spawned.
+    Started,
+
+    /// The dependency has passed its readiness checks (see
+    /// [`crate::readiness::Readiness`]). For commands without readiness
+    /// checks, this is the same as [`DependencyCondition::Started`].
+    /// This is the default.
+    Ready,
+
+    /// The dependency has exited successfully, e.g. for one-shot
+    /// setup tasks such as database migrations.
+    Completed,
+}
+
+/// A command that has to be ready (or completed) before
+/// the command depending on it is spawned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dependency {
+    pub command_idx: usize,
+    pub condition: DependencyCondition,
+}
+
+impl Dependency {
+    /// Parses a dependency of the form `<name|index>[:started|:ready|:completed]`
+    pub fn parse(input: &str, names: &[String]) -> Result<Self> {
+        let (reference, condition) = match input.rsplit_once(':') {
+            Some((reference, "started")) => (reference, DependencyCondition::Started),
+            Some((reference, "ready")) => (reference, DependencyCondition::Ready),
+            Some((reference, "completed")) => (reference, DependencyCondition::Completed),
+            Some((_, condition)) => bail!(
+                "Unknown dependency condition '{}'. Possible values are: started, ready, \
+                 completed",
+                condition
+            ),
+            None => (input, DependencyCondition::Ready),
+        };
+
+        let command_idx = find_command(names, reference)
+            .ok_or_else(|| anyhow!("Dependency '{}' does not exist", reference))?;
+
+        Ok(Dependency {
+            command_idx,
+            condition,
+        })
+    }
+}
+
+/// Makes sure that the dependency graph (given as the list of dependencies of
+/// each command) does not contain any cycles, as the commands involved could
+/// never be started.
+pub fn check_for_cycles(dependencies: &[Vec<Dependency>], names: &[String]) -> Result<()> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        Unvisited,
+        InProgress,
+        Done,
+    }
+
+    fn visit(
+        idx: usize,
+        dependencies: &[Vec<Dependency>],
+        marks: &mut [Mark],
+        path: &mut Vec<usize>,
+    ) -> Option<Vec<usize>> {
+        match marks[idx] {
+            Mark::Done => return None,
+            Mark::InProgress => {
+                let start = path.iter().position(|x| *x == idx).unwrap();
+                let mut cycle = path[start..].to_vec();
+                cycle.push(idx);
+                return Some(cycle);
+            }
+            Mark::Unvisited => {}
+        }
+
+        marks[idx] = Mark::InProgress;
+        path.push(idx);
+        for dependency in &dependencies[idx] {
+            if let Some(cycle) = visit(dependency.command_idx, dependencies, marks, path) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        marks[idx] = Mark::Done;
+
+        None
+    }
+
+    let mut marks = vec![Mark::Unvisited; dependencies.len()];
+    for idx in 0..dependencies.len() {
+        if let Some(cycle) = visit(idx, dependencies, &mut marks, &mut vec![]) {
+            let cycle: Vec<_> = cycle.iter().map(|idx| names[*idx].as_str()).collect();
+            bail!("Commands have a dependency cycle: {}", cycle.join(" -> "));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names() -> Vec<String> {
+        ["db", "api", "web"].iter().map(|s| s.to_string()).collect()
+    }
+
+    fn deps(names: &[String], input: &[&[&str]]) -> Vec<Vec<Dependency>> {
+        input
+            .iter()
+            .map(|deps| {
+                deps.iter()
+                    .map(|dep| Dependency::parse(dep, names).unwrap())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let names = names();
+        assert_eq!(
+            Dependency {
+                command_idx: 1,
+                condition: DependencyCondition::Ready
+            },
+            Dependency::parse("api", &names).unwrap()
+        );
+        assert_eq!(
+            Dependency {
+                command_idx: 0,
+                condition: DependencyCondition::Completed
+            },
+            Dependency::parse("0:completed", &names).unwrap()
+        );
+        assert_eq!(
+            DependencyCondition::Started,
+            Dependency::parse("web:started", &names).unwrap().condition
+        );
+        assert!(Dependency::parse("foo", &names).is_err());
+        assert!(Dependency::parse("db:ready-ish", &names).is_err());
+    }
+
+    #[test]
+    fn test_check_for_cycles() {
+        let names = names();
+        assert!(check_for_cycles(&deps(&names, &[&[], &["db"], &["db", "api"]]), &names).is_ok());
+
+        let err = check_for_cycles(&deps(&names, &[&["web"], &["db"], &["api"]]), &names)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            "Commands have a dependency cycle: db -> web -> api -> db",
+            err
+        );
+
+        assert!(check_for_cycles(&deps(&names, &[&["db"], &[], &[]]), &names).is_err());
+    }
+}
@@ -1,24 +1,37 @@
 use std::future::Future;
+use std::path::PathBuf;
 use std::process::ExitStatus;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::time::Duration;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use anyhow::{anyhow, Context, Result};
-use log::{debug, error, trace};
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, trace};
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
+use ratatui::crossterm::event::KeyEvent;
 use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
-use tokio::signal;
+use tokio::process::Child;
 use tokio::sync::{mpsc, oneshot};
-use tokio::task::JoinSet;
+use tokio::task::{AbortHandle, JoinHandle, JoinSet};
+use tokio::time::{Interval, MissedTickBehavior};
 
-use crate::{Command, Config};
-
-macro_rules! rly_println {
-    ($cmd:expr, $($arg:tt)*) => {{
-        if !$cmd.disable_output() {
-            println!($($arg)*);
-        }
-    }};
-}
+use crate::config::find_command;
+use crate::control::{CommandInfo, Control, Reply, Request, Response};
+use crate::dependencies::DependencyCondition;
+use crate::input::{read_stdin, route, InputWriter};
+use crate::kill::{
+    group_alive, parse_signal, signal_group, wait_for_group, wait_for_group_to_vanish, KillStep,
+};
+use crate::output::{Output, Stream};
+use crate::pty::{visible_line, Pty};
+use crate::restart::FailureHistory;
+use crate::runner::{CommandResult, RunResult};
+use crate::success::exit_code;
+use crate::timings::Timings;
+use crate::tui::{Action, Row, RowStatus, Tui, REDRAW_INTERVAL};
+use crate::watch::WatchSignal;
+use crate::{reaper, signals, Command, Config};
 
 #[derive(Debug)]
 enum Event {
@@ -28,185 +41,1256 @@ enum Event {
     },
     Output {
         line: String,
+        stream: Stream,
         command_idx: usize,
     },
     Exit {
         status: ExitStatus,
         command_idx: usize,
     },
+    /// All readiness checks of the command passed, see [`crate::readiness::Readiness`]
+    Ready {
+        command_idx: usize,
+        elapsed: Duration,
+    },
+    /// The readiness checks of the command did not pass within their timeout
+    NotReady { command_idx: usize },
+    /// The command did not exit in time after being asked to, so it is
+    /// sent the next signal of its [`crate::kill::KillSequence`]
+    KillEscalated {
+        command_idx: usize,
+        signal: &'static str,
+        elapsed: Duration,
+    },
+    /// Files watched by the command changed, see [`crate::watch::Watch`]
+    FilesChanged {
+        command_idx: usize,
+        paths: Vec<PathBuf>,
+    },
 }
 
 const OUTPUT_CHANNEL_BUFFER_SIZE: usize = 128;
 
+/// How often the processes that are still running are reported after an interrupt
+const COUNTDOWN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long to wait for the remaining output of a process after it exited
+const OUTPUT_GRACE_PERIOD: Duration = Duration::from_millis(100);
+
+/// How long to wait for processes that are left behind to be gone after
+/// killing them on shutdown
+const LEFTOVER_KILL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Lifecycle of a command, as tracked by the event loop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CommandStatus {
+    /// Waiting for its dependencies to be satisfied
+    Waiting,
+
+    /// An [`Event::Spawn`] has been sent, but the process is not running yet
+    Starting,
+
+    Running,
+
+    /// Exited and won't be restarted
+    Exited,
+
+    /// Never started, because one of its dependencies failed
+    Skipped,
+}
+
+/// Whether a command has passed its readiness checks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReadyState {
+    Pending,
+
+    /// Once ready, a command stays ready, even if it is restarted
+    Ready,
+
+    /// The readiness checks did not pass within their timeout
+    TimedOut,
+}
+
+enum DependencyState {
+    Pending,
+    Satisfied,
+    Failed(usize),
+}
+
 struct State {
-    config: &'static Config,
-    children_alive: AtomicUsize,
+    config: Arc<Config>,
+    statuses: Vec<CommandStatus>,
     task_set: JoinSet<Result<()>>,
     kill_channels: Vec<Option<oneshot::Sender<()>>>,
     tx: mpsc::Sender<Event>,
+
+    /// Set once all processes have been asked to terminate. Processes
+    /// are not restarted after this point.
+    shutting_down: bool,
+
+    /// Reports the processes that are still being waited for once `rly`
+    /// has been interrupted, see [`State::interrupt`]
+    countdown: Option<Interval>,
+
+    /// When the processes that are being terminated after an interrupt
+    /// are killed with `SIGKILL`, if their kill sequences end with it
+    kill_deadline: Option<Instant>,
+
+    /// Set if `rly` was interrupted twice, and exits with this
+    /// code rather than the one of the commands
+    forced_exit_code: Option<i32>,
+
+    /// The most recent exit code of each command, if it exited at least once
+    exit_codes: Vec<Option<i32>>,
+
+    /// Index of the command for every exit that happened, in order
+    exit_order: Vec<usize>,
+
+    /// How often each command has been restarted since it last counted
+    /// as healthy, see [`crate::restart::Restart::should_reset`]
+    restarts: Vec<u32>,
+
+    /// When the current process of each command was spawned
+    spawned_at: Vec<Option<Instant>>,
+
+    /// Recent failures of each command, see [`crate::restart::CrashLoop`]
+    failures: Vec<FailureHistory>,
+
+    ready: Vec<ReadyState>,
+
+    /// Tasks waiting for the readiness checks of running commands to pass
+    probes: Vec<Option<AbortHandle>>,
+
+    /// Tasks waiting to spawn commands (e.g. for `--restart-after`), see
+    /// [`State::request_spawn`]
+    spawns: Vec<Option<AbortHandle>>,
+
+    /// Notifies the probe of a command once a line of its output
+    /// matched [`crate::readiness::Readiness::log`]
+    log_matched: Vec<Option<oneshot::Sender<()>>>,
+
+    /// Tasks forwarding file changes for watched commands
+    watchers: Vec<AbortHandle>,
+
+    /// Set for commands that have been terminated because files they watch
+    /// changed (or because they were restarted from the terminal UI), and
+    /// that are started again once they exited.
+    pending_restarts: Vec<bool>,
+
+    /// Set for commands that have been stopped from the terminal UI, which
+    /// are not restarted and don't cause other commands to be killed
+    stopped: Vec<bool>,
+
+    /// Where input for running commands is written to, see
+    /// [`crate::cli::Args::handle_input`]
+    inputs: Vec<Option<InputWriter>>,
+
+    /// Input for commands that are about to be spawned
+    pending_input: Vec<Vec<String>>,
+
+    /// Set once the stdin of `rly` ended, after which the input of
+    /// processes is closed as soon as they are spawned
+    input_closed: bool,
+
+    /// Process groups of exited commands that still had processes in
+    /// them, which are killed once everything else shut down
+    leftover_groups: Vec<LeftoverGroup>,
+
+    output: Output,
+
+    /// See [`crate::cli::Args::tui`]
+    tui: Option<Tui>,
+
+    /// See [`crate::cli::Args::control`]
+    control: Option<Control>,
+
+    timings: Timings,
 }
 
 impl State {
-    pub fn shut_down(self) -> JoinSet<Result<()>> {
-        self.task_set
+    /// Stops all tasks that would keep the event channel open without any
+    /// processes running, so that draining it doesn't wait for them
+    pub fn shut_down(self) -> (JoinSet<Result<()>>, Output) {
+        let tasks = self.probes.iter().chain(&self.spawns).flatten();
+        for task in tasks.chain(&self.watchers) {
+            task.abort();
+        }
+        (self.task_set, self.output)
+    }
+
+    fn alive(&self) -> usize {
+        self.statuses
+            .iter()
+            .filter(|status| **status == CommandStatus::Running)
+            .count()
+    }
+
+    /// Returns true if no process is running and none is about to be spawned,
+    /// either directly or because files watched by an exited command change.
+    fn is_finished(&self) -> bool {
+        let busy = self
+            .statuses
+            .iter()
+            .any(|status| matches!(status, CommandStatus::Starting | CommandStatus::Running));
+
+        let watching = !self.shutting_down
+            && self
+                .config
+                .commands
+                .iter()
+                .zip(&self.statuses)
+                .any(|(cmd, status)| cmd.watch.is_some() && *status == CommandStatus::Exited);
+
+        // The terminal UI stays open until it is quit, as
+        // commands can still be started from there
+        let interactive = !self.shutting_down && self.tui.is_some();
+
+        !busy && !watching && !interactive
+    }
+
+    /// Sends an [`Event::Spawn`] for the given command, optionally after a delay
+    fn request_spawn(&mut self, command_idx: usize, is_restart: bool, delay: Duration) {
+        self.statuses[command_idx] = CommandStatus::Starting;
+
+        let tx = self.tx.clone();
+        let spawn = self.task_set.spawn(async move {
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+
+            tx.send(Event::Spawn {
+                command_idx,
+                is_restart,
+            })
+            .await
+            .context("Failed to send spawn message")
+        });
+        self.spawns[command_idx] = Some(spawn);
+    }
+
+    fn dependency_state(&self, command_idx: usize) -> DependencyState {
+        let mut result = DependencyState::Satisfied;
+
+        for dependency in &self.config.commands[command_idx].depends_on {
+            let idx = dependency.command_idx;
+            let status = self.statuses[idx];
+            let exit_code = self.exit_codes[idx];
+
+            let ready = self.ready[idx];
+
+            let satisfied = match dependency.condition {
+                DependencyCondition::Started => {
+                    status == CommandStatus::Running || exit_code.is_some()
+                }
+                DependencyCondition::Ready => ready == ReadyState::Ready,
+                DependencyCondition::Completed => exit_code == Some(0),
+            };
+
+            let failed = match dependency.condition {
+                DependencyCondition::Started => status == CommandStatus::Skipped,
+                DependencyCondition::Ready => {
+                    ready == ReadyState::TimedOut
+                        || (matches!(status, CommandStatus::Exited | CommandStatus::Skipped)
+                            && !satisfied)
+                }
+                DependencyCondition::Completed => {
+                    matches!(status, CommandStatus::Exited | CommandStatus::Skipped) && !satisfied
+                }
+            };
+
+            if failed {
+                return DependencyState::Failed(idx);
+            } else if !satisfied {
+                result = DependencyState::Pending;
+            }
+        }
+
+        result
+    }
+
+    /// Starts all waiting commands whose dependencies are satisfied, and skips
+    /// those that have a dependency which failed.
+    fn schedule_waiting_commands(&mut self) {
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for command_idx in 0..self.statuses.len() {
+                if self.statuses[command_idx] != CommandStatus::Waiting {
+                    continue;
+                }
+
+                match self.dependency_state(command_idx) {
+                    DependencyState::Pending => {}
+                    DependencyState::Satisfied => {
+                        self.request_spawn(command_idx, false, Duration::ZERO);
+                    }
+                    DependencyState::Failed(dependency_idx) => {
+                        let cmd = &self.config.commands[command_idx];
+                        self.output.status(
+                            command_idx,
+                            &format!(
+                                "{} skipped, because its dependency '{}' failed",
+                                cmd.command, self.config.names[dependency_idx]
+                            ),
+                        );
+                        self.output.finish_group(command_idx, true);
+
+                        // Skipping a command may cause commands depending on it to fail
+                        self.statuses[command_idx] = CommandStatus::Skipped;
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Starts waiting for the readiness checks of a freshly spawned command.
+    /// Commands without readiness checks are ready right away.
+    fn probe_readiness(&mut self, command_idx: usize) {
+        let cmd = Arc::clone(&self.config.commands[command_idx]);
+        let Some(readiness) = &cmd.readiness else {
+            self.ready[command_idx] = ReadyState::Ready;
+            return;
+        };
+
+        if self.ready[command_idx] == ReadyState::Ready {
+            return;
+        }
+        self.ready[command_idx] = ReadyState::Pending;
+
+        let log_matched = readiness.log.as_ref().map(|_| {
+            let (tx, rx) = oneshot::channel();
+            self.log_matched[command_idx] = Some(tx);
+            rx
+        });
+
+        let tx = self.tx.clone();
+        let probe = self.task_set.spawn(async move {
+            let readiness = cmd.readiness.as_ref().unwrap();
+            let event = match readiness.wait(log_matched).await {
+                Some(elapsed) => Event::Ready {
+                    command_idx,
+                    elapsed,
+                },
+                None => Event::NotReady { command_idx },
+            };
+
+            tx.send(event)
+                .await
+                .context("Failed to send readiness message")
+        });
+        self.probes[command_idx] = Some(probe);
+    }
+
+    fn stop_probe(&mut self, command_idx: usize) {
+        if let Some(probe) = self.probes[command_idx].take() {
+            probe.abort();
+        }
+        self.log_matched[command_idx] = None;
+    }
+
+    /// Starts forwarding changes to files watched by the given command
+    fn watch(&mut self, command_idx: usize) -> Result<()> {
+        let Some(watch) = &self.config.commands[command_idx].watch else {
+            return Ok(());
+        };
+
+        let mut changes = watch.changes()?;
+        let tx = self.tx.clone();
+        let watcher = self.task_set.spawn(async move {
+            while let Some(paths) = changes.next().await {
+                tx.send(Event::FilesChanged { command_idx, paths })
+                    .await
+                    .context("Failed to send file change message")?;
+            }
+            Ok(())
+        });
+        self.watchers.push(watcher);
+
+        Ok(())
+    }
+
+    /// Forwards a line read from the stdin of `rly` to the command it is meant for
+    async fn forward_input(&mut self, line: &str) {
+        let (command_idx, input) =
+            route(line, &self.config.names, self.config.default_input_target);
+
+        match self.statuses[command_idx] {
+            CommandStatus::Running => {
+                let Some(writer) = &mut self.inputs[command_idx] else {
+                    return;
+                };
+                if let Err(err) = writer.write_line(input).await {
+                    debug!("Failed to forward input to command {command_idx}: {err}");
+                    self.inputs[command_idx] = None;
+                }
+            }
+            CommandStatus::Waiting | CommandStatus::Starting => {
+                self.pending_input[command_idx].push(input.to_string());
+            }
+            CommandStatus::Exited | CommandStatus::Skipped => {
+                self.output.notice(
+                    None,
+                    &format!(
+                        "--> Unable to forward input, '{}' is not running",
+                        self.config.names[command_idx]
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Closes the input of all running processes, once the stdin of `rly` ended
+    async fn close_input(&mut self) {
+        self.input_closed = true;
+        for writer in self.inputs.iter_mut().filter_map(Option::take) {
+            if let Err(err) = writer.close().await {
+                debug!("Failed to close input: {err}");
+            }
+        }
+    }
+
+    /// Terminates all processes, because `rly` received the given signal
+    /// (e.g. `SIGINT` by pressing Ctrl-C). Returns whether the event loop
+    /// should stop right away.
+    ///
+    /// Processes are given the time their kill sequences allow for to shut
+    /// down gracefully. Being interrupted again while shutting down kills all
+    /// of them right away instead.
+    fn interrupt(&mut self, message: &str, signal: Signal) -> bool {
+        self.output.notice(None, message);
+        if self.shutting_down {
+            self.output.notice(None, "--> Killing all processes..");
+            self.kill_all_now();
+            self.forced_exit_code = Some(128 + signal as i32);
+            return true;
+        }
+
+        self.output.notice(None, "Terminating all processes..");
+        self.kill_all();
+
+        let now = Instant::now();
+        self.kill_deadline = self.time_to_kill().map(|time_to_kill| now + time_to_kill);
+        let mut countdown =
+            tokio::time::interval_at((now + COUNTDOWN_INTERVAL).into(), COUNTDOWN_INTERVAL);
+        countdown.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        self.countdown = Some(countdown);
+
+        // Nothing might be running, e.g. if only watched commands
+        // that already exited are left.
+        self.is_finished()
+    }
+
+    /// Indices of the commands that are currently running
+    fn running(&self) -> Vec<usize> {
+        (0..self.config.commands.len())
+            .filter(|idx| self.statuses[*idx] == CommandStatus::Running)
+            .collect()
+    }
+
+    /// How long it takes until all running processes are killed with
+    /// `SIGKILL`, if all of their kill sequences end with it
+    fn time_to_kill(&self) -> Option<Duration> {
+        self.running()
+            .into_iter()
+            .map(|idx| self.config.commands[idx].kill.time_to_kill())
+            .try_fold(Duration::ZERO, |max, time| time.map(|time| max.max(time)))
+    }
+
+    /// Reports the processes that are still being waited for after an interrupt
+    fn print_countdown(&mut self) {
+        let names: Vec<_> = self
+            .running()
+            .into_iter()
+            .map(|idx| self.config.names[idx].as_str())
+            .collect();
+        if names.is_empty() {
+            return;
+        }
+
+        let message = match self.kill_deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return;
+                }
+                format!(
+                    "--> Waiting for {} to exit, killing them in {:.0}s (press Ctrl-C again to \
+                     kill them now)",
+                    names.join(", "),
+                    remaining.as_secs_f64().ceil()
+                )
+            }
+            None => format!(
+                "--> Waiting for {} to exit (press Ctrl-C again to kill them)",
+                names.join(", ")
+            ),
+        };
+        self.output.notice(None, &message);
+    }
+
+    /// Kills the process groups of all running processes with `SIGKILL`
+    fn kill_all_now(&mut self) {
+        self.shutting_down = true;
+        for idx in self.running() {
+            let cmd = &self.config.commands[idx];
+            let pgid = Pid::from_raw(cmd.pid.load(Ordering::Relaxed) as i32);
+            if let Err(err) = signal_group(pgid, Signal::SIGKILL) {
+                debug!("Failed to kill {cmd}: {err}");
+            }
+        }
+    }
+
+    /// Forwards a signal received by `rly` to the process groups of the
+    /// running commands, see [`crate::cli::Args::signal_targets`]
+    fn forward_signal(&mut self, signal: Signal) {
+        let targets: Vec<_> = self
+            .running()
+            .into_iter()
+            .filter(|idx| self.config.commands[*idx].forward_signals)
+            .collect();
+        if targets.is_empty() {
+            return;
+        }
+
+        let names: Vec<_> = targets
+            .iter()
+            .map(|idx| self.config.names[*idx].as_str())
+            .collect();
+        self.output.notice(
+            None,
+            &format!("--> Forwarding {} to {}", signal.as_str(), names.join(", ")),
+        );
+
+        for idx in targets {
+            let cmd = &self.config.commands[idx];
+            let pgid = Pid::from_raw(cmd.pid.load(Ordering::Relaxed) as i32);
+            if let Err(err) = signal_group(pgid, signal) {
+                debug!("Failed to forward {signal} to {cmd}: {err}");
+            }
+        }
+    }
+
+    /// Asks all running processes to terminate, and cancels those about to
+    /// be spawned, e.g. restarts waiting for `--restart-after`
+    fn kill_all(&mut self) {
+        self.shutting_down = true;
+        for opt in self.kill_channels.iter_mut() {
+            if let Some(tx) = opt.take() {
+                tx.send(()).unwrap_or(());
+            }
+        }
+
+        for command_idx in 0..self.statuses.len() {
+            if let Some(spawn) = self.spawns[command_idx].take() {
+                spawn.abort();
+                if self.statuses[command_idx] == CommandStatus::Starting {
+                    self.statuses[command_idx] = CommandStatus::Exited;
+                }
+            }
+        }
+    }
+
+    /// Terminates a running command and starts it again once it exited, or
+    /// starts a command that is no longer running. Returns false if the
+    /// command is in no state to be restarted, e.g. because it is stopping.
+    fn restart_command(&mut self, command_idx: usize) -> bool {
+        match self.statuses[command_idx] {
+            CommandStatus::Running => {
+                // Already being terminated
+                let Some(tx) = self.kill_channels[command_idx].take() else {
+                    return false;
+                };
+                self.output.status(command_idx, "restarting");
+                self.pending_restarts[command_idx] = true;
+                tx.send(()).unwrap_or(());
+                true
+            }
+            CommandStatus::Exited | CommandStatus::Skipped => self.start_command(command_idx),
+            // About to be started anyway
+            CommandStatus::Waiting | CommandStatus::Starting => false,
+        }
+    }
+
+    /// Terminates a command, which is then neither restarted nor counts as a
+    /// failure that other commands are killed for. Returns false if the
+    /// command is not running.
+    fn stop_command(&mut self, command_idx: usize) -> bool {
+        match self.statuses[command_idx] {
+            CommandStatus::Running => {
+                self.stopped[command_idx] = true;
+                self.pending_restarts[command_idx] = false;
+                if let Some(tx) = self.kill_channels[command_idx].take() {
+                    self.output.status(command_idx, "stopping");
+                    tx.send(()).unwrap_or(());
+                }
+                true
+            }
+            // Waiting to be restarted, which is skipped
+            CommandStatus::Starting => {
+                self.stopped[command_idx] = true;
+                true
+            }
+            CommandStatus::Waiting | CommandStatus::Exited | CommandStatus::Skipped => false,
+        }
+    }
+
+    /// Starts a command that is no longer running. Returns false if it is
+    /// still running (or about to be), or if `rly` is shutting down.
+    fn start_command(&mut self, command_idx: usize) -> bool {
+        if self.shutting_down
+            || !matches!(
+                self.statuses[command_idx],
+                CommandStatus::Exited | CommandStatus::Skipped
+            )
+        {
+            return false;
+        }
+
+        self.restarts[command_idx] = 0;
+        self.request_spawn(command_idx, true, Duration::ZERO);
+        true
+    }
+
+    /// Sends a signal to the process group of a running command, and
+    /// returns false if it is not running
+    fn signal_command(&mut self, command_idx: usize, signal: Signal) -> bool {
+        if self.statuses[command_idx] != CommandStatus::Running {
+            return false;
+        }
+
+        let cmd = &self.config.commands[command_idx];
+        let pgid = Pid::from_raw(cmd.pid.load(Ordering::Relaxed) as i32);
+        if let Err(err) = signal_group(pgid, signal) {
+            debug!("Failed to send {signal} to {cmd}: {err}");
+        }
+        true
+    }
+
+    /// Carries out a request received on the control socket. Requests for a
+    /// single command fail if it is in no state to carry them out, while
+    /// requests for all commands apply to those that are.
+    fn handle_request(&mut self, request: Request) -> Reply {
+        let result = match request {
+            Request::List => {
+                let commands = (0..self.config.commands.len())
+                    .map(|idx| self.command_info(idx))
+                    .collect();
+                return Reply::Response(Response {
+                    commands: Some(commands),
+                    ..Response::ok()
+                });
+            }
+            Request::Logs {
+                target,
+                lines,
+                follow,
+            } => match self.target(target.as_deref()) {
+                Ok(target) => match &mut self.control {
+                    Some(control) => return control.logs(target, lines, follow),
+                    None => unreachable!("Requests are only received on the control socket"),
+                },
+                Err(err) => Err(err),
+            },
+            Request::Start { target } => {
+                self.apply_request(target.as_deref(), "start", Self::start_command)
+            }
+            Request::Stop { target } => {
+                self.apply_request(target.as_deref(), "stop", Self::stop_command)
+            }
+            Request::Restart { target } => {
+                self.apply_request(target.as_deref(), "restart", Self::restart_command)
+            }
+            Request::Signal { target, signal } => parse_signal(&signal).and_then(|signal| {
+                self.apply_request(target.as_deref(), "signal", |state, idx| {
+                    state.signal_command(idx, signal)
+                })
+            }),
+        };
+
+        Reply::Response(match result {
+            Ok(()) => Response::ok(),
+            Err(err) => Response::error(&err),
+        })
+    }
+
+    /// Resolves the target of a request, which is either a single
+    /// command (by name or index) or all of them
+    fn target(&self, target: Option<&str>) -> Result<Option<usize>> {
+        match target {
+            Some(target) => match find_command(&self.config.names, target) {
+                Some(idx) => Ok(Some(idx)),
+                None => bail!("Unknown command '{}'", target),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn apply_request(
+        &mut self,
+        target: Option<&str>,
+        action: &str,
+        apply: impl Fn(&mut Self, usize) -> bool,
+    ) -> Result<()> {
+        match self.target(target)? {
+            Some(idx) => {
+                if !apply(self, idx) {
+                    bail!(
+                        "Unable to {} '{}' while it is {}",
+                        action,
+                        self.config.names[idx],
+                        self.row(idx).status.as_str()
+                    );
+                }
+            }
+            None => {
+                for idx in 0..self.config.commands.len() {
+                    apply(self, idx);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// A command as listed on the control socket
+    fn command_info(&self, command_idx: usize) -> CommandInfo {
+        let row = self.row(command_idx);
+        CommandInfo {
+            index: command_idx,
+            name: row.name.to_string(),
+            command: self.config.commands[command_idx].command.clone(),
+            status: row.status.as_str().to_string(),
+            pid: row.pid,
+            uptime_ms: row.uptime.map(|uptime| uptime.as_millis() as u64),
+            exit_code: match row.status {
+                RowStatus::Exited(code) => code,
+                _ => None,
+            },
+            restarts: self.restarts[command_idx],
+        }
+    }
+
+    /// Handles a key pressed in the terminal UI, and returns whether
+    /// the event loop should stop right away
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let Some(tui) = &mut self.tui else {
+            return false;
+        };
+
+        let stop = match tui.handle_key(key, self.config.commands.len()) {
+            Some(Action::Restart(idx)) => {
+                self.restart_command(idx);
+                false
+            }
+            Some(Action::Stop(idx)) => {
+                self.stop_command(idx);
+                false
+            }
+            Some(Action::Start(idx)) => {
+                self.start_command(idx);
+                false
+            }
+            Some(Action::Quit) => self.interrupt("Quitting", Signal::SIGINT),
+            None => false,
+        };
+
+        self.draw();
+        stop
     }
-}
 
-async fn handle_ctrlc() -> Result<()> {
-    match signal::ctrl_c().await {
-        Ok(_) => Ok(()),
-        Err(e) => {
-            eprintln!("Unable to listen to Ctrl-C: {}", e);
-            Err(anyhow!(e))
+    /// What the terminal UI shows about a command
+    fn row(&self, command_idx: usize) -> Row<'_> {
+        let cmd = &self.config.commands[command_idx];
+        let status = match self.statuses[command_idx] {
+            CommandStatus::Waiting => RowStatus::Waiting,
+            CommandStatus::Starting if self.spawned_at[command_idx].is_some() => {
+                RowStatus::Restarting
+            }
+            CommandStatus::Starting => RowStatus::Starting,
+            CommandStatus::Running if self.kill_channels[command_idx].is_none() => {
+                RowStatus::Stopping
+            }
+            CommandStatus::Running
+                if cmd.readiness.is_some() && self.ready[command_idx] == ReadyState::Ready =>
+            {
+                RowStatus::Ready
+            }
+            CommandStatus::Running => RowStatus::Running,
+            CommandStatus::Exited => RowStatus::Exited(self.exit_codes[command_idx]),
+            CommandStatus::Skipped => RowStatus::Skipped,
+        };
+
+        let running = self.statuses[command_idx] == CommandStatus::Running;
+        Row {
+            name: &self.config.names[command_idx],
+            status,
+            pid: running.then(|| cmd.pid.load(Ordering::Relaxed)),
+            uptime: self.spawned_at[command_idx]
+                .filter(|_| running)
+                .map(|spawned_at| spawned_at.elapsed()),
+        }
+    }
+
+    /// Redraws the terminal UI, if it is shown
+    fn draw(&mut self) {
+        // The rows borrow the state, which the terminal UI is part of
+        let Some(mut tui) = self.tui.take() else {
+            return;
+        };
+
+        let rows: Vec<_> = (0..self.config.commands.len())
+            .map(|idx| self.row(idx))
+            .collect();
+        if let Some(logs) = self.output.logs() {
+            if let Err(err) = tui.draw(&rows, logs) {
+                debug!("Failed to draw the terminal UI: {:?}", err);
+            }
+        }
+
+        self.tui = Some(tui);
+    }
+
+    /// Leaves the terminal UI, after which output is printed again
+    fn close_tui(&mut self) {
+        if self.tui.take().is_some() {
+            self.output.close_logs();
         }
     }
 }
 
-fn should_kill_others(state: &State, status: &ExitStatus) -> bool {
-    // If the kill channels are empty, that means that we've already
+fn should_kill_others(state: &State, cmd: &Command, status: &ExitStatus) -> bool {
+    // If we're already shutting down, that means that we've already
     // sent kill signals to the processes. In that case, we shouldn't
     // try to do it again.
-    if state.kill_channels.is_empty() {
+    if state.shutting_down {
         return false;
     }
 
-    if state.config.kill_others_on_fail {
+    if cmd.kill_others_on_fail {
         return !status.success();
     }
 
-    if !state.config.kill_others {
+    if !cmd.kill_others {
         return false;
     }
 
     true
 }
 
-async fn handle_next_event(
-    config: &'static Config,
-    state: &mut State,
-    rx: &mut mpsc::Receiver<Event>,
-) -> Result<bool> {
-    match rx.recv().await {
+/// Handles an event received from the spawned tasks, and returns whether
+/// the event loop should keep running
+async fn handle_event(config: &Config, state: &mut State, event: Option<Event>) -> Result<bool> {
+    match event {
         Some(Event::Spawn {
             command_idx,
             is_restart,
         }) => {
-            handle_spawn_event(state, command_idx, is_restart).await?;
+            state.spawns[command_idx] = None;
+            // A restart may have been scheduled before we started shutting
+            // down, or before the command was stopped
+            if state.shutting_down || std::mem::take(&mut state.stopped[command_idx]) {
+                state.statuses[command_idx] = CommandStatus::Exited;
+                return Ok(!state.is_finished());
+            }
+
+            if let Err(err) = handle_spawn_event(state, command_idx, is_restart).await {
+                // Counts as a failure of the command, which e.g. kills the others
+                state.output.status(command_idx, &format!("{:#}", err));
+                // The uptime of a previous process doesn't count towards this one
+                state.spawned_at[command_idx] = None;
+                return Ok(handle_exit(config, state, command_idx, spawn_failure()));
+            }
+            state.probe_readiness(command_idx);
+            state.schedule_waiting_commands();
             Ok(true)
         }
 
-        Some(Event::Output { command_idx, line }) => {
+        Some(Event::Output {
+            command_idx,
+            stream,
+            line,
+        }) => {
             let cmd = config.commands.get(command_idx).unwrap();
-            rly_println!(cmd, "{} {}", cmd.prefix(), line);
+            state.output.line(command_idx, stream, &line);
+            if let Some(control) = &mut state.control {
+                control.record(command_idx, &config.names[command_idx], stream, &line);
+            }
+
+            if state.log_matched[command_idx].is_some() {
+                let pattern = cmd.readiness.as_ref().and_then(|r| r.log.as_ref());
+                if pattern.is_some_and(|pattern| pattern.is_match(&line)) {
+                    let tx = state.log_matched[command_idx].take().unwrap();
+                    tx.send(()).unwrap_or(());
+                }
+            }
+
             Ok(true)
         }
 
-        Some(Event::Exit {
+        Some(Event::Ready {
             command_idx,
-            status,
+            elapsed,
         }) => {
-            let cmd = config.commands.get(command_idx).unwrap();
-            let full_command = &config.commands.get(command_idx).unwrap().command;
-            rly_println!(
-                cmd,
-                "{} {} exited with {}",
-                cmd.prefix(),
-                full_command,
-                status
+            state.probes[command_idx] = None;
+
+            // The process may have exited while this event was in flight
+            if state.statuses[command_idx] != CommandStatus::Running {
+                return Ok(true);
+            }
+
+            state.output.status(
+                command_idx,
+                &format!("ready after {:.1}s", elapsed.as_secs_f64()),
             );
+            state.ready[command_idx] = ReadyState::Ready;
 
-            // -1 because `fetch_sub` returns the state _before_ the subtraction operation
-            let num_processes = state.children_alive.fetch_sub(1, Ordering::Relaxed) - 1;
-            debug!("{cmd} exited. Alive processes now: {}", num_processes);
+            if !state.shutting_down {
+                state.schedule_waiting_commands();
+            }
+            Ok(true)
+        }
 
-            // We're mirroring the behaviour of concurrently, where restarts only happen if
-            // the process exited with a non-success code. This seems to make sense, but maybe
-            // there is a case for an option to always restart?
-            if !status.success()
-                && (cmd.restart_indefinitely
-                    || cmd.restart_tries.fetch_sub(1, Ordering::Relaxed) > 0)
-            {
-                let tx = state.tx.clone();
-                state.task_set.spawn(async move {
-                    if !config.restart_after.is_zero() {
-                        tokio::time::sleep(config.restart_after).await;
-                    }
+        Some(Event::FilesChanged { command_idx, paths }) => {
+            let cmd = config.commands.get(command_idx).unwrap();
+            if state.shutting_down {
+                return Ok(true);
+            }
 
-                    tx.send(Event::Spawn {
-                        command_idx,
-                        is_restart: true,
-                    })
-                    .await
-                    .context("Failed to send spawn message")
-                });
+            let paths: Vec<_> = paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            let paths = paths.join(", ");
 
-                Ok(true)
-            } else if should_kill_others(state, &status) {
-                rly_println!(cmd, "--> Sending SIGTERM to other processes..");
-                for mut opt in state.kill_channels.drain(..) {
-                    if let Some(tx) = opt.take() {
+            match (
+                state.statuses[command_idx],
+                cmd.watch.as_ref().unwrap().signal,
+            ) {
+                (CommandStatus::Running, WatchSignal::Term) => {
+                    state
+                        .output
+                        .status(command_idx, &format!("{} changed, restarting", paths));
+                    state.pending_restarts[command_idx] = true;
+                    if let Some(tx) = state.kill_channels[command_idx].take() {
                         tx.send(()).unwrap_or(());
                     }
                 }
+                (CommandStatus::Running, WatchSignal::Hup) => {
+                    state
+                        .output
+                        .status(command_idx, &format!("{} changed, reloading", paths));
+                    state.signal_command(command_idx, Signal::SIGHUP);
+                }
+                (CommandStatus::Exited, _) => {
+                    state
+                        .output
+                        .status(command_idx, &format!("{} changed, restarting", paths));
+                    state.request_spawn(command_idx, true, Duration::ZERO);
+                }
+                // The command is about to be started anyway, or never will be
+                _ => {}
+            }
+
+            Ok(true)
+        }
+
+        Some(Event::KillEscalated {
+            command_idx,
+            signal,
+            elapsed,
+        }) => {
+            state.output.status(
+                command_idx,
+                &kill_escalated_message(config, command_idx, signal, elapsed),
+            );
+            Ok(true)
+        }
 
-                Ok(true)
-            } else if num_processes < 1 {
-                debug!("No more processes. Stopping main loop.");
-                Ok(false)
-            } else {
-                Ok(true)
+        Some(Event::NotReady { command_idx }) => {
+            let cmd = config.commands.get(command_idx).unwrap();
+            state.stop_probe(command_idx);
+
+            if state.statuses[command_idx] != CommandStatus::Running {
+                return Ok(true);
             }
+
+            let timeout = cmd.readiness.as_ref().unwrap().timeout;
+            state.output.status(
+                command_idx,
+                &format!(
+                    "{} not ready after {:.1}s, giving up",
+                    cmd.command,
+                    timeout.as_secs_f64()
+                ),
+            );
+            state.ready[command_idx] = ReadyState::TimedOut;
+
+            // Not becoming ready is treated like a failure
+            if !state.shutting_down && (cmd.kill_others || cmd.kill_others_on_fail) {
+                state
+                    .output
+                    .notice(Some(command_idx), &kill_others_message(config));
+                state.kill_all();
+            }
+
+            if !state.shutting_down {
+                state.schedule_waiting_commands();
+            }
+            Ok(true)
+        }
+
+        Some(Event::Exit {
+            command_idx,
+            status,
+        }) => {
+            let cmd = config.commands.get(command_idx).unwrap();
+            state.output.exited(command_idx, &status);
+
+            state.stop_probe(command_idx);
+            state.timings.exited(command_idx, status);
+            state.inputs[command_idx] = None;
+
+            // Processes started by the command may outlive it
+            let pgid = Pid::from_raw(cmd.pid.load(Ordering::Relaxed) as i32);
+            if group_alive(pgid) {
+                debug!("{cmd} exited, but its process group is still alive");
+                state.leftover_groups.push(LeftoverGroup {
+                    command_idx,
+                    pgid,
+                    vanished: tokio::spawn(wait_for_group_to_vanish(pgid)),
+                });
+            }
+
+            // Terminated because files it watches changed (or to be restarted),
+            // so this is neither a failure nor the final exit of the command.
+            if std::mem::take(&mut state.pending_restarts[command_idx]) && !state.shutting_down {
+                state.output.finish_group(command_idx, false);
+                state.request_spawn(command_idx, true, Duration::ZERO);
+                return Ok(true);
+            }
+
+            Ok(handle_exit(config, state, command_idx, status))
         }
 
         None => Ok(false),
     }
 }
 
-pub async fn event_loop(config: &'static Config) -> Result<()> {
+/// Restarts the command that exited with the given status, or kills the
+/// other commands if it should, and returns whether the event loop should
+/// keep running
+fn handle_exit(config: &Config, state: &mut State, command_idx: usize, status: ExitStatus) -> bool {
+    let cmd = config.commands.get(command_idx).unwrap();
+    state.exit_codes[command_idx] = Some(exit_code(&status));
+    state.exit_order.push(command_idx);
+
+    let uptime = state.spawned_at[command_idx].map(|spawned_at| spawned_at.elapsed());
+    let healthy = uptime.is_some_and(|uptime| cmd.restart.should_reset(uptime));
+    if healthy {
+        state.restarts[command_idx] = 0;
+    }
+
+    let stopped = std::mem::take(&mut state.stopped[command_idx]);
+    let restarts = state.restarts[command_idx];
+    let mut restart =
+        !state.shutting_down && !stopped && cmd.restart.should_restart(&status, restarts);
+
+    // Processes that keep exiting right away are in a crash loop regardless
+    // of their exit code, e.g. with `--restart-policy always`
+    let mut crash_loop = false;
+    if let Some(limit) = cmd.restart.crash_loop.filter(|_| restart && !healthy) {
+        if state.failures[command_idx].record(Instant::now(), &limit) {
+            state.output.status(
+                command_idx,
+                &format!(
+                    "crash loop detected ({} failures in {}s), giving up",
+                    limit.failures,
+                    limit.window.as_secs_f64()
+                ),
+            );
+            restart = false;
+            crash_loop = true;
+        }
+    }
+
+    if restart {
+        let delay = cmd.restart.delay(restarts);
+        if !delay.is_zero() {
+            state.output.status(
+                command_idx,
+                &format!("restarting in {:.1}s", delay.as_secs_f64()),
+            );
+        }
+
+        state.restarts[command_idx] += 1;
+        state.request_spawn(command_idx, true, delay);
+    } else {
+        state.statuses[command_idx] = CommandStatus::Exited;
+    }
+    state.output.finish_group(command_idx, !restart);
+
+    debug!("{cmd} exited. Alive processes now: {}", state.alive());
+
+    let kill_others = !stopped
+        && (should_kill_others(state, cmd, &status)
+            || (crash_loop && config.kill_others_on_crash_loop && !state.shutting_down));
+    if !restart && state.alive() > 0 && kill_others {
+        state
+            .output
+            .notice(Some(command_idx), &kill_others_message(config));
+        state.kill_all();
+    }
+
+    if !state.shutting_down {
+        state.schedule_waiting_commands();
+    }
+
+    if state.is_finished() {
+        debug!("No more processes. Stopping main loop.");
+        false
+    } else {
+        true
+    }
+}
+
+/// The status a command that could not be spawned counts as having exited
+/// with, which is what shells exit with for commands they can't run
+fn spawn_failure() -> ExitStatus {
+    #[cfg(unix)]
+    use std::os::unix::process::ExitStatusExt;
+    #[cfg(windows)]
+    use std::os::windows::process::ExitStatusExt;
+
+    #[cfg(unix)]
+    return ExitStatus::from_raw(127 << 8);
+    #[cfg(windows)]
+    return ExitStatus::from_raw(127);
+}
+
+/// Runs all commands of the given [`Config`], printing everything to the
+/// given [`Output`]. The result holds the exit code that `rly` should exit
+/// with, as determined by [`Config::success`].
+pub async fn event_loop(config: Arc<Config>, output: Output) -> Result<RunResult> {
     // This is the channel that is used to communicate everything that's happening
     // in the spawned processes back here, where output is handled.
     let (tx, mut rx) = mpsc::channel::<Event>(OUTPUT_CHANNEL_BUFFER_SIZE);
 
     let mut state = State {
-        config,
-        children_alive: AtomicUsize::new(0),
+        config: Arc::clone(&config),
+        statuses: vec![CommandStatus::Waiting; config.commands.len()],
         task_set: JoinSet::new(),
         kill_channels: vec![],
         tx,
+        shutting_down: false,
+        countdown: None,
+        kill_deadline: None,
+        forced_exit_code: None,
+        exit_codes: vec![None; config.commands.len()],
+        exit_order: vec![],
+        restarts: vec![0; config.commands.len()],
+        spawned_at: vec![None; config.commands.len()],
+        failures: (0..config.commands.len())
+            .map(|_| FailureHistory::default())
+            .collect(),
+        ready: vec![ReadyState::Pending; config.commands.len()],
+        probes: (0..config.commands.len()).map(|_| None).collect(),
+        spawns: (0..config.commands.len()).map(|_| None).collect(),
+        log_matched: (0..config.commands.len()).map(|_| None).collect(),
+        watchers: vec![],
+        pending_restarts: vec![false; config.commands.len()],
+        stopped: vec![false; config.commands.len()],
+        inputs: (0..config.commands.len()).map(|_| None).collect(),
+        pending_input: vec![vec![]; config.commands.len()],
+        input_closed: false,
+        leftover_groups: vec![],
+        output,
+        tui: None,
+        control: None,
+        timings: Timings::new(config.commands.len()),
     };
 
     for _ in 0..config.commands.len() {
         state.kill_channels.push(None);
     }
 
-    for (command_idx, _) in config.commands.iter().enumerate() {
-        state
-            .tx
-            .send(Event::Spawn {
-                command_idx,
-                is_restart: false,
-            })
-            .await
-            .unwrap()
+    for command_idx in 0..config.commands.len() {
+        state.watch(command_idx)?;
+    }
+
+    let mut keys = None;
+    let mut redraw = None;
+    if config.tui {
+        let (tui, rx) = Tui::start()?;
+        state.tui = Some(tui);
+        keys = Some(rx);
+
+        let mut interval = tokio::time::interval(REDRAW_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        redraw = Some(interval);
+    }
+
+    let mut requests = None;
+    if let Some(path) = &config.control_socket {
+        let (control, rx) = Control::listen(path, config.commands.len())?;
+        state.control = Some(control);
+        requests = Some(rx);
     }
 
+    // Commands are only spawned once their dependencies are satisfied,
+    // which for now only applies to commands without any dependencies.
+    state.schedule_waiting_commands();
+
+    let mut input = config.handle_input.then(read_stdin);
+    // Both stop once the event loop returns
+    let mut signals = config.handle_signals.then(signals::listen).transpose()?;
+    let _reaper = config.init.then(reaper::start).transpose()?;
+
     loop {
         tokio::select! {
-            _ = handle_ctrlc() => {
-                rly_println!(config, "Ctrl-C issued");
-                if state.kill_channels.is_empty() {
-                  break;
-                } else {
-                    rly_println!(config, "Terminating all processes..");
-                    for mut opt in state.kill_channels.drain(..) {
-                        if let Some(tx) = opt.take() {
-                            tx.send(()).unwrap_or(());
-                        }
+            line = next_input(&mut input) => match line {
+                Some(line) => state.forward_input(&line).await,
+                None => {
+                    input = None;
+                    state.close_input().await;
+                }
+            },
+            Some(signal) = next_input(&mut signals) => {
+                let reason = match signal {
+                    Signal::SIGINT => "Ctrl-C issued",
+                    Signal::SIGTERM => "SIGTERM received",
+                    _ => {
+                        state.forward_signal(signal);
+                        continue;
+                    }
+                };
+                if state.interrupt(reason, signal) {
+                    break;
+                }
+            },
+            key = next_input(&mut keys) => match key {
+                Some(key) => {
+                    if state.handle_key(key) {
+                        break;
                     }
                 }
+                None => keys = None,
             },
-            result = handle_next_event(config, &mut state, &mut rx) => {
-                if !result? {
+            request = next_input(&mut requests) => match request {
+                Some((request, reply)) => {
+                    if reply.send(state.handle_request(request)).is_err() {
+                        debug!("Control client disconnected before receiving a reply");
+                    }
+                }
+                None => requests = None,
+            },
+            _ = next_tick(&mut redraw) => state.draw(),
+            _ = next_tick(&mut state.countdown) => state.print_countdown(),
+            // Only receiving is raced against the other branches. Handling an
+            // event must not be cancelled halfway, e.g. after spawning a process.
+            event = rx.recv() => {
+                if !handle_event(&config, &mut state, event).await? {
                   break;
                 }
             },
@@ -214,21 +1298,66 @@ pub async fn event_loop(config: &'static Config) -> Result<()> {
     }
 
     trace!("Main event loop has stopped.");
+    state.close_tui();
+
+    // These may keep the output of commands open, which is drained below
+    kill_leftovers(&config, &mut state.output, &state.leftover_groups).await;
+
+    let exit_code = state.forced_exit_code.unwrap_or_else(|| {
+        config
+            .success
+            .exit_code(&state.exit_codes, &state.exit_order)
+    });
+
+    let timings = config.timings.then(|| {
+        let commands: Vec<_> = config
+            .commands
+            .iter()
+            .map(|cmd| cmd.command.as_str())
+            .collect();
+        state
+            .timings
+            .table(&config.names, &commands, &config.timestamp_format)
+    });
+
+    let commands = (0..config.commands.len())
+        .map(|idx| CommandResult {
+            name: config.names[idx].clone(),
+            command: config.commands[idx].command.clone(),
+            exit_code: state.exit_codes[idx],
+        })
+        .collect();
 
     // We need to drop the sending end of this channel, so that the receiving end will
     // close once all messages have been delivered. If we don't drop this end here, the
     // draining loop below will wait indefinitely.
-    let mut task_set = state.shut_down();
+    let (mut task_set, mut output) = state.shut_down();
 
     while let Some(event) = rx.recv().await {
         match event {
-            Event::Output { command_idx, line } => {
-                let cmd = config.commands.get(command_idx).unwrap();
-                rly_println!(cmd, "{} {}", cmd.prefix(), line);
-            }
-            x => {
-                error!("{:?}", x)
-            }
+            Event::Output {
+                command_idx,
+                stream,
+                line,
+            } => output.line(command_idx, stream, &line),
+            Event::KillEscalated {
+                command_idx,
+                signal,
+                elapsed,
+            } => output.status(
+                command_idx,
+                &kill_escalated_message(&config, command_idx, signal, elapsed),
+            ),
+            // Processes that are still exiting when the event
+            // loop is stopped, e.g. after being killed by Ctrl-C
+            Event::Exit {
+                command_idx,
+                status,
+            } => output.exited(command_idx, &status),
+            Event::Spawn { .. }
+            | Event::Ready { .. }
+            | Event::NotReady { .. }
+            | Event::FilesChanged { .. } => {}
         }
     }
 
@@ -238,33 +1367,97 @@ pub async fn event_loop(config: &'static Config) -> Result<()> {
         }
     }
 
-    Ok(())
+    output.flush()?;
+
+    if let Some(timings) = timings {
+        output.notice(None, "--> Timings:");
+        for line in timings.lines() {
+            output.notice(None, line);
+        }
+    }
+
+    Ok(RunResult {
+        exit_code,
+        commands,
+    })
 }
 
 #[allow(clippy::manual_async_fn)]
 fn output_listener<R: AsyncRead + Unpin>(
-    name: &'static str,
+    stream: Stream,
+    command_idx: usize,
+    cmd: Arc<Command>,
+    reader: R,
+    tx: mpsc::Sender<Event>,
+) -> impl Future<Output = Result<()>> {
+    async move {
+        trace!("{stream} reader task for {cmd} started");
+
+        let mut reader = BufReader::new(reader).lines();
+        while let Some(line) = reader.next_line().await? {
+            tx.send(Event::Output {
+                line,
+                stream,
+                command_idx,
+            })
+            .await?
+        }
+
+        trace!("{stream} reader task for {cmd} stopped");
+        Ok(())
+    }
+}
+
+/// Waits for the next tick of the given interval, if there is one
+async fn next_tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Waits for the next line read by [`read_stdin`] (or the next key pressed in
+/// the terminal UI, or the next control request), if input is handled and
+/// stdin did not end yet
+async fn next_input<T>(input: &mut Option<mpsc::Receiver<T>>) -> Option<T> {
+    match input {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Like [`output_listener`], for commands attached to a pseudo-terminal.
+/// Everything is reported as stdout, as that is what the terminal merges.
+#[allow(clippy::manual_async_fn)]
+fn pty_listener<R: AsyncRead + Unpin>(
     command_idx: usize,
-    cmd: &'static Command,
+    cmd: Arc<Command>,
     reader: R,
     tx: mpsc::Sender<Event>,
 ) -> impl Future<Output = Result<()>> {
     async move {
-        trace!("{name} reader task for {cmd} started");
+        trace!("pty reader task for {cmd} started");
 
         let mut reader = BufReader::new(reader).lines();
         while let Some(line) = reader.next_line().await? {
-            tx.send(Event::Output { line, command_idx }).await?
+            tx.send(Event::Output {
+                line: visible_line(&line).to_string(),
+                stream: Stream::Stdout,
+                command_idx,
+            })
+            .await?
         }
 
-        trace!("{name} reader task for {cmd} stopped");
+        trace!("pty reader task for {cmd} stopped");
         Ok(())
     }
 }
 
 async fn handle_spawn_event(state: &mut State, command_idx: usize, is_restart: bool) -> Result<()> {
-    let cmd = state.config.commands.get(command_idx).unwrap();
-    let mut child = cmd.tokio_command().spawn().expect("Failed to spawn child");
+    let cmd = Arc::clone(&state.config.commands[command_idx]);
+    let (child, pty) = cmd.spawn()?;
 
     let pid = child
         .id()
@@ -272,32 +1465,95 @@ async fn handle_spawn_event(state: &mut State, command_idx: usize, is_restart: b
     cmd.pid.store(pid, Ordering::Relaxed);
     debug!("Spawned command {cmd}");
 
-    if !state.config.raw {
+    // The process would be left running without anyone waiting for it
+    if let Err(err) = supervise(state, command_idx, cmd, child, pty, pid).await {
+        if let Err(err) = signal_group(Pid::from_raw(pid as i32), Signal::SIGKILL) {
+            debug!("Failed to kill process group {pid}: {err}");
+        }
+        return Err(err);
+    }
+
+    state.statuses[command_idx] = CommandStatus::Running;
+    state.spawned_at[command_idx] = Some(Instant::now());
+    state.timings.started(command_idx);
+    state.output.spawned(command_idx, is_restart);
+
+    Ok(())
+}
+
+/// Forwards the output (and input) of a freshly spawned process, and
+/// starts the task that waits for it to exit
+async fn supervise(
+    state: &mut State,
+    command_idx: usize,
+    cmd: Arc<Command>,
+    mut child: Child,
+    pty: Option<Pty>,
+    pid: u32,
+) -> Result<()> {
+    let mut listeners = vec![];
+    let mut resizer = None;
+    if let Some(pty) = &pty {
+        listeners.push(tokio::spawn(pty_listener(
+            command_idx,
+            Arc::clone(&cmd),
+            pty.stream()?,
+            state.tx.clone(),
+        )));
+        resizer = Some(tokio::spawn(pty.forward_resizes()?));
+    } else if !cmd.raw {
         let stdout = child
             .stdout
             .take()
             .ok_or_else(|| anyhow!("Failed to acquire stdout handle"))?;
 
-        state.task_set.spawn(output_listener(
-            "stdout",
+        listeners.push(tokio::spawn(output_listener(
+            Stream::Stdout,
             command_idx,
-            cmd,
+            Arc::clone(&cmd),
             stdout,
             state.tx.clone(),
-        ));
+        )));
 
         let stderr = child
             .stderr
             .take()
             .ok_or_else(|| anyhow!("Failed to acquire stderr handle"))?;
 
-        state.task_set.spawn(output_listener(
-            "stderr",
+        listeners.push(tokio::spawn(output_listener(
+            Stream::Stderr,
             command_idx,
-            cmd,
+            Arc::clone(&cmd),
             stderr,
             state.tx.clone(),
-        ));
+        )));
+    }
+
+    if cmd.handle_input {
+        let writer = match &pty {
+            Some(pty) => Some(InputWriter::Pty(pty.stream()?)),
+            None => child.stdin.take().map(InputWriter::Pipe),
+        };
+
+        if let Some(mut writer) = writer {
+            let mut result = Ok(());
+            for line in std::mem::take(&mut state.pending_input[command_idx]) {
+                result = writer.write_line(&line).await;
+                if result.is_err() {
+                    break;
+                }
+            }
+
+            if state.input_closed && result.is_ok() {
+                result = writer.close().await;
+            } else if result.is_ok() {
+                state.inputs[command_idx] = Some(writer);
+            }
+
+            if let Err(err) = result {
+                debug!("Failed to forward input to {cmd}: {err}");
+            }
+        }
     }
 
     // This is the task that waits for the child's exit status
@@ -305,66 +1561,185 @@ async fn handle_spawn_event(state: &mut State, command_idx: usize, is_restart: b
     state.kill_channels[command_idx] = Some(kill_tx);
     let tx = state.tx.clone();
     state.task_set.spawn(async move {
-        tokio::select! {
+        let status = tokio::select! {
             status = child.wait() => {
                 let status = status?;
                 trace!("Task with pid {pid} exited with {status}");
-
-                tx.send(Event::Exit {
-                    command_idx,
-                    status,
-                })
-                .await?;
+                status
             }
 
             _ = kill_rx => {
                 trace!("Received kill signal for {cmd}");
+                let pgid = Pid::from_raw(pid as i32);
+                let status = terminate(&mut child, pgid, command_idx, &cmd, &tx).await?;
+                debug!("{cmd} killed with {status}");
+                status
+            }
+        };
+        reaper::forget(pid);
 
-                if cfg!(target_os = "windows") {
-                  if let Err(e) = child.start_kill() {
-                    eprintln!("Could not kill process: {:?}", e);
-                  };
-                } else {
-                  // On Unix, it is preferable to send the SIGTERM signal as it allows
-                  // the process to shut down gracefully
-                  let pid = nix::unistd::Pid::from_raw(child.id().unwrap() as i32);
-                  nix::sys::signal::kill(pid, nix::sys::signal::Signal::SIGTERM).expect("Failed to send SIGTERM");
-                }
-
-                let status =
-                  match tokio::time::timeout(Duration::from_secs(2), child.wait()).await {
-                    Ok(Ok(status)) => {
-                      status
-                    },
-                    Ok(Err(e)) => {
-                      eprintln!("Could not wait for process");
-                      Err(e)?
-                    }
-                    Err(_) => {
-                      eprintln!("Process did not terminate within 2s, sending SIGKILL...");
-                      let pid = nix::unistd::Pid::from_raw(child.id().unwrap() as i32);
-                      nix::sys::signal::kill(pid, nix::sys::signal::Signal::SIGKILL).expect("Failed to send SIGKILL");
-                      ExitStatus::default()
-                    },
-                  };
+        if let Some(resizer) = resizer {
+            resizer.abort();
+        }
+        // Keep the terminal open until here, so that e.g. a shell does not
+        // receive SIGHUP before it exited on its own
+        drop(pty);
 
-                debug!("{cmd} killed with {status}");
-                tx.send(Event::Exit {
-                    command_idx,
-                    status,
-                }).await?;
+        // Output is read by separate tasks, so the last lines may not have been
+        // forwarded yet. Processes that left children behind may keep the pipes
+        // open though, in which case their output keeps being forwarded after
+        // the exit.
+        let output_done = async {
+            for listener in listeners.iter_mut() {
+                if let Err(err) = flatten_errors(listener.await) {
+                    debug!("Output listener for {cmd} failed with error: {:?}", err);
+                }
             }
+        };
+        if tokio::time::timeout(OUTPUT_GRACE_PERIOD, output_done)
+            .await
+            .is_err()
+        {
+            trace!("Output of {cmd} is still open after it exited");
         }
 
+        tx.send(Event::Exit {
+            command_idx,
+            status,
+        })
+        .await?;
+
         Ok(())
     });
 
-    state.children_alive.fetch_add(1, Ordering::SeqCst);
-    if is_restart {
-        rly_println!(cmd, "{} {} restarted", cmd.prefix(), cmd.command);
+    Ok(())
+}
+
+/// Names the signal that other processes are terminated with, unless
+/// their kill signals differ
+fn kill_others_message(config: &Config) -> String {
+    let mut signals = config.commands.iter().map(|cmd| cmd.kill.signal());
+    let first = signals.next();
+    match first {
+        Some(signal) if signals.all(|other| other == signal) => {
+            format!("--> Sending {} to other processes..", signal.as_str())
+        }
+        _ => "--> Terminating other processes..".to_string(),
     }
+}
 
-    Ok(())
+fn kill_escalated_message(
+    config: &Config,
+    command_idx: usize,
+    signal: &str,
+    elapsed: Duration,
+) -> String {
+    format!(
+        "{} still running after {:.1}s, sending {}",
+        config.commands[command_idx].command,
+        elapsed.as_secs_f64(),
+        signal
+    )
+}
+
+/// Terminates the process by going through the [`crate::kill::KillSequence`]
+/// of the command, and reports every signal but the first one
+async fn terminate(
+    child: &mut Child,
+    pgid: Pid,
+    command_idx: usize,
+    cmd: &Command,
+    tx: &mpsc::Sender<Event>,
+) -> Result<ExitStatus> {
+    let started = Instant::now();
+
+    for (idx, step) in cmd.kill.steps.iter().enumerate() {
+        match *step {
+            KillStep::Wait(timeout) => {
+                if let Ok(status) = tokio::time::timeout(timeout, wait_for_exit(child, pgid)).await
+                {
+                    return Ok(status?);
+                }
+            }
+            KillStep::Signal(signal) => {
+                if idx > 0 {
+                    tx.send(Event::KillEscalated {
+                        command_idx,
+                        signal: signal.as_str(),
+                        elapsed: started.elapsed(),
+                    })
+                    .await?;
+                }
+
+                if cfg!(target_os = "windows") {
+                    if let Err(err) = child.start_kill() {
+                        debug!("Failed to kill {cmd}: {err}");
+                    }
+                } else if let Err(err) = signal_group(pgid, signal) {
+                    // On Unix, signals allow the process to shut down gracefully
+                    debug!("Failed to send {signal} to {cmd}: {err}");
+                }
+            }
+        }
+    }
+
+    Ok(wait_for_exit(child, pgid).await?)
+}
+
+/// Waits for a process that is being terminated, along with the processes
+/// it started. These may exit after the shell, and are given the same time
+/// to shut down gracefully.
+async fn wait_for_exit(child: &mut Child, pgid: Pid) -> std::io::Result<ExitStatus> {
+    let status = child.wait().await?;
+    wait_for_group(pgid).await;
+    Ok(status)
+}
+
+/// A process group of an exited command that still had processes in it
+struct LeftoverGroup {
+    command_idx: usize,
+    pgid: Pid,
+
+    /// Finishes once the group no longer exists. Its id may belong to an
+    /// unrelated group by the time `rly` shuts down, which must not be killed.
+    vanished: JoinHandle<()>,
+}
+
+/// Kills the processes that commands left behind, once everything else shut down
+async fn kill_leftovers(config: &Config, output: &mut Output, groups: &[LeftoverGroup]) {
+    let mut killed = vec![];
+    for group in groups {
+        let (command_idx, pgid) = (group.command_idx, group.pgid);
+        let vanished = group.vanished.is_finished();
+        group.vanished.abort();
+        if vanished || !group_alive(pgid) {
+            continue;
+        }
+
+        output.status(
+            command_idx,
+            &format!(
+                "{} left processes behind, sending SIGKILL to process group {}",
+                config.commands[command_idx].command, pgid
+            ),
+        );
+        if let Err(err) = signal_group(pgid, Signal::SIGKILL) {
+            debug!("Failed to kill process group {pgid}: {err}");
+        }
+        killed.push(pgid);
+    }
+
+    let gone = async {
+        for &pgid in &killed {
+            wait_for_group(pgid).await;
+        }
+    };
+    if tokio::time::timeout(LEFTOVER_KILL_TIMEOUT, gone)
+        .await
+        .is_err()
+    {
+        debug!("Processes left behind are still alive after sending SIGKILL");
+    }
 }
 
 fn flatten_errors<T, E1, E2>(res: Result<Result<T, E1>, E2>) -> Result<T>
@@ -0,0 +1,233 @@
+use std::fs::File;
+use std::io::{self, BufWriter, IntoInnerError, Seek, SeekFrom, Write};
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use log::debug;
+
+/// How much output is kept in memory per command, before the rest
+/// is written to a temporary file
+const MAX_BUFFERED_BYTES: usize = 1024 * 1024;
+
+/// Order in which the output of commands is printed, see [`crate::cli::Args::group_order`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GroupOrder {
+    /// Print output as soon as a command exits. This is the default.
+    #[default]
+    Exit,
+
+    /// Print output in the order the commands were given, as if they
+    /// were run sequentially
+    Command,
+}
+
+impl FromStr for GroupOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "exit" => Ok(GroupOrder::Exit),
+            "command" => Ok(GroupOrder::Command),
+            _ => bail!(
+                "Unknown group order '{}'. Possible values are: exit, command",
+                s
+            ),
+        }
+    }
+}
+
+/// Buffers the output of each command, so that it can be printed in a
+/// contiguous block once the command exited (see [`crate::cli::Args::group`]).
+pub struct OutputGroups {
+    order: GroupOrder,
+    buffers: Vec<Buffer>,
+    max_buffered_bytes: usize,
+
+    /// Commands that won't produce any more output (except when they
+    /// are restarted by watch mode)
+    done: Vec<bool>,
+
+    /// With [`GroupOrder::Command`], all commands before this one
+    /// have been printed
+    next: usize,
+}
+
+#[derive(Default)]
+struct Buffer {
+    lines: Vec<String>,
+    size: usize,
+
+    /// Temporary file holding the lines that did not fit into memory,
+    /// buffered so that writing a line doesn't take a syscall
+    file: Option<BufWriter<File>>,
+}
+
+impl OutputGroups {
+    pub fn new(count: usize, order: GroupOrder) -> Self {
+        Self::with_limit(count, order, MAX_BUFFERED_BYTES)
+    }
+
+    fn with_limit(count: usize, order: GroupOrder, max_buffered_bytes: usize) -> Self {
+        OutputGroups {
+            order,
+            buffers: (0..count).map(|_| Buffer::default()).collect(),
+            max_buffered_bytes,
+            done: vec![false; count],
+            next: 0,
+        }
+    }
+
+    pub fn push(&mut self, command_idx: usize, line: String) {
+        let buffer = &mut self.buffers[command_idx];
+        buffer.push(line);
+
+        if buffer.file.is_none() && buffer.size > self.max_buffered_bytes {
+            if let Err(err) = buffer.spill() {
+                debug!("Failed to spill output to a temporary file, keeping it in memory: {err}");
+            }
+        }
+    }
+
+    /// Prints the output of the given command, if its turn has come. If
+    /// `is_final` is false, the command is going to be restarted, which
+    /// only prints its output so far when grouping in [`GroupOrder::Exit`].
+    pub fn finish(
+        &mut self,
+        command_idx: usize,
+        is_final: bool,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        match self.order {
+            GroupOrder::Exit => self.buffers[command_idx].flush(out),
+            GroupOrder::Command => {
+                if !is_final {
+                    return Ok(());
+                }
+
+                self.done[command_idx] = true;
+                if command_idx < self.next {
+                    // Restarted by watch mode after its turn already came
+                    return self.buffers[command_idx].flush(out);
+                }
+
+                while self.next < self.done.len() && self.done[self.next] {
+                    self.buffers[self.next].flush(out)?;
+                    self.next += 1;
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Prints all remaining output, in command order
+    pub fn flush_all(&mut self, out: &mut impl Write) -> io::Result<()> {
+        for buffer in self.buffers.iter_mut() {
+            buffer.flush(out)?;
+        }
+        self.next = self.buffers.len();
+        Ok(())
+    }
+}
+
+impl Buffer {
+    fn push(&mut self, line: String) {
+        if let Some(file) = &mut self.file {
+            match writeln!(file, "{}", line) {
+                Ok(_) => return,
+                Err(err) => debug!("Failed to write output to a temporary file: {err}"),
+            }
+        }
+
+        self.size += line.len() + 1;
+        self.lines.push(line);
+    }
+
+    /// Moves the lines held in memory into a temporary file
+    fn spill(&mut self) -> io::Result<()> {
+        let mut file = BufWriter::new(tempfile::tempfile()?);
+        for line in &self.lines {
+            writeln!(file, "{}", line)?;
+        }
+
+        self.file = Some(file);
+        self.lines.clear();
+        self.size = 0;
+        Ok(())
+    }
+
+    fn flush(&mut self, out: &mut impl Write) -> io::Result<()> {
+        // Lines in memory are always newer than those in the file, see `push`
+        if let Some(file) = self.file.take() {
+            let mut file = file.into_inner().map_err(IntoInnerError::into_error)?;
+            file.seek(SeekFrom::Start(0))?;
+            io::copy(&mut file, out)?;
+        }
+
+        for line in self.lines.drain(..) {
+            writeln!(out, "{}", line)?;
+        }
+        self.size = 0;
+
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(out: Vec<u8>) -> String {
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_exit_order() {
+        let mut groups = OutputGroups::new(2, GroupOrder::Exit);
+        let mut out = vec![];
+
+        groups.push(0, "a1".to_string());
+        groups.push(1, "b1".to_string());
+        groups.push(0, "a2".to_string());
+        groups.finish(1, true, &mut out).unwrap();
+        groups.finish(0, true, &mut out).unwrap();
+
+        assert_eq!("b1\na1\na2\n", output(out));
+    }
+
+    #[test]
+    fn test_command_order() {
+        let mut groups = OutputGroups::new(3, GroupOrder::Command);
+        let mut out = vec![];
+
+        groups.push(0, "a1".to_string());
+        groups.push(1, "b1".to_string());
+        groups.push(2, "c1".to_string());
+        groups.finish(2, true, &mut out).unwrap();
+        groups.finish(1, false, &mut out).unwrap();
+        assert!(out.is_empty());
+
+        groups.push(1, "b2".to_string());
+        groups.finish(0, true, &mut out).unwrap();
+        assert_eq!("a1\n", output(out.clone()));
+
+        groups.finish(1, true, &mut out).unwrap();
+        assert_eq!("a1\nb1\nb2\nc1\n", output(out));
+    }
+
+    #[test]
+    fn test_spills_to_file() {
+        let mut groups = OutputGroups::with_limit(1, GroupOrder::Exit, 10);
+        let mut out = vec![];
+
+        let lines: Vec<_> = (0..100).map(|i| format!("line {}", i)).collect();
+        for line in &lines {
+            groups.push(0, line.clone());
+        }
+        assert!(groups.buffers[0].file.is_some());
+        assert!(groups.buffers[0].lines.is_empty());
+
+        groups.finish(0, true, &mut out).unwrap();
+        assert_eq!(format!("{}\n", lines.join("\n")), output(out));
+    }
+}
@@ -0,0 +1,94 @@
+use std::io::BufRead;
+
+use tokio::io::{self, AsyncWriteExt};
+use tokio::process::ChildStdin;
+use tokio::sync::mpsc;
+
+use crate::config::find_command;
+use crate::pty::PtyStream;
+
+/// Character that ends the input of a terminal in canonical mode (Ctrl-D)
+const TERMINAL_EOF: u8 = 0x04;
+
+/// Where input for a running command is written to
+pub enum InputWriter {
+    Pipe(ChildStdin),
+    Pty(PtyStream),
+}
+
+impl InputWriter {
+    pub async fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let line = format!("{}\n", line);
+        match self {
+            InputWriter::Pipe(stdin) => stdin.write_all(line.as_bytes()).await,
+            InputWriter::Pty(pty) => pty.write_all(line.as_bytes()).await,
+        }
+    }
+
+    /// Ends the input of the command. Closing a pipe is enough, but
+    /// the end of the input has to be typed into a terminal.
+    pub async fn close(self) -> io::Result<()> {
+        match self {
+            InputWriter::Pipe(stdin) => {
+                drop(stdin);
+                Ok(())
+            }
+            InputWriter::Pty(mut pty) => pty.write_all(&[TERMINAL_EOF]).await,
+        }
+    }
+}
+
+/// Reads the stdin of `rly` line by line, see [`crate::cli::Args::handle_input`].
+/// The returned channel is closed once stdin ends.
+///
+/// Reading from stdin can't be cancelled, so this uses a thread of its own
+/// rather than a task, which would keep the runtime from shutting down.
+pub fn read_stdin() -> mpsc::Receiver<String> {
+    let (tx, rx) = mpsc::channel(1);
+
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if tx.blocking_send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    rx
+}
+
+/// Returns the index of the command that a line of input is meant for,
+/// along with the input itself. Lines starting with the name or index of a
+/// command followed by a colon (e.g. `server:rs`) are sent to that command,
+/// and all others to `default_target`.
+pub fn route<'a>(line: &'a str, names: &[String], default_target: usize) -> (usize, &'a str) {
+    if let Some((target, input)) = line.split_once(':') {
+        if let Some(idx) = find_command(names, target) {
+            return (idx, input);
+        }
+    }
+
+    (default_target, line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route() {
+        let names = vec!["server".to_string(), "web".to_string()];
+
+        assert_eq!((0, "rs"), route("server:rs", &names, 1));
+        assert_eq!((1, "help"), route("1:help", &names, 0));
+        assert_eq!((1, "rs"), route("rs", &names, 1));
+        assert_eq!(
+            (0, "http://localhost"),
+            route("http://localhost", &names, 0)
+        );
+        assert_eq!((0, ""), route("server:", &names, 1));
+    }
+}
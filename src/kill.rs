@@ -0,0 +1,319 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use nix::errno::Errno;
+use nix::sys::signal::{killpg, Signal};
+use nix::unistd::Pid;
+
+/// How long to wait for a process after the first signal before killing it
+pub const DEFAULT_KILL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often to check whether the processes of a group are gone
+const GROUP_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How often to check whether a process group that a command left behind
+/// still exists, see [`wait_for_group_to_vanish`]
+const LEFTOVER_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A step of a [`KillSequence`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KillStep {
+    Signal(Signal),
+
+    /// Wait for the process to exit, for at most this long
+    Wait(Duration),
+}
+
+/// The steps taken to terminate a process, see [`crate::cli::Args::kill_signal`].
+/// If the process is still running after the last step, it is waited for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KillSequence {
+    pub steps: Vec<KillStep>,
+}
+
+impl Default for KillSequence {
+    fn default() -> Self {
+        KillSequence::escalating(Signal::SIGTERM, DEFAULT_KILL_TIMEOUT)
+    }
+}
+
+impl KillSequence {
+    /// Builds the sequence from the options of a command. A single signal is
+    /// followed by `SIGKILL` after the given timeout, while sequences (such as
+    /// `SIGINT,10s,SIGTERM,5s,SIGKILL`) are taken as they are.
+    pub fn new(signal: Option<&str>, timeout: Option<Duration>) -> Result<Self> {
+        let timeout = timeout.unwrap_or(DEFAULT_KILL_TIMEOUT);
+        let Some(signal) = signal else {
+            return Ok(KillSequence::escalating(Signal::SIGTERM, timeout));
+        };
+
+        let steps = signal
+            .split(',')
+            .map(|step| step.trim().parse())
+            .collect::<Result<Vec<KillStep>>>()?;
+
+        match steps[..] {
+            [KillStep::Signal(signal)] => Ok(KillSequence::escalating(signal, timeout)),
+            [KillStep::Signal(_), ..] => Ok(KillSequence { steps }),
+            _ => bail!(
+                "Kill signal sequence '{}' has to start with a signal",
+                signal
+            ),
+        }
+    }
+
+    /// The signal that is sent first
+    pub fn signal(&self) -> Signal {
+        match self.steps.first() {
+            Some(KillStep::Signal(signal)) => *signal,
+            _ => unreachable!("Kill sequences start with a signal"),
+        }
+    }
+
+    /// How long it takes until the process is killed with `SIGKILL`,
+    /// or `None` if the sequence does not end with it
+    pub fn time_to_kill(&self) -> Option<Duration> {
+        let mut elapsed = Duration::ZERO;
+        for step in &self.steps {
+            match step {
+                KillStep::Wait(timeout) => elapsed += *timeout,
+                KillStep::Signal(Signal::SIGKILL) => return Some(elapsed),
+                KillStep::Signal(_) => {}
+            }
+        }
+
+        None
+    }
+
+    fn escalating(signal: Signal, timeout: Duration) -> Self {
+        if signal == Signal::SIGKILL {
+            return KillSequence {
+                steps: vec![KillStep::Signal(signal)],
+            };
+        }
+
+        KillSequence {
+            steps: vec![
+                KillStep::Signal(signal),
+                KillStep::Wait(timeout),
+                KillStep::Signal(Signal::SIGKILL),
+            ],
+        }
+    }
+}
+
+/// Sends a signal to all processes of the group led by the given process.
+/// Every command is spawned as the leader of a process group of its own (see
+/// [`crate::command::Command::tokio_command`]), so this also reaches the
+/// processes it started, e.g. `node` started by `npm run`.
+pub fn signal_group(pgid: Pid, signal: Signal) -> nix::Result<()> {
+    killpg(pgid, signal)
+}
+
+/// Whether a process group with the given id exists, even if all of its
+/// processes exited and only wait to be reaped. Its id can't be taken by
+/// another group before this returns false.
+pub fn group_exists(pgid: Pid) -> bool {
+    !matches!(killpg(pgid, None), Err(Errno::ESRCH))
+}
+
+/// Whether any process of the given group is still running
+pub fn group_alive(pgid: Pid) -> bool {
+    if !group_exists(pgid) {
+        return false;
+    }
+
+    // Processes that exited remain members of their group until they are
+    // reaped, which may take a while or never happen at all, e.g. in
+    // containers whose init process does not reap orphans
+    #[cfg(target_os = "linux")]
+    if let Ok(running) = running_members(pgid) {
+        return running > 0;
+    }
+
+    true
+}
+
+/// Counts the processes of the given group that did not exit yet
+#[cfg(target_os = "linux")]
+fn running_members(pgid: Pid) -> std::io::Result<usize> {
+    let mut running = 0;
+    for entry in std::fs::read_dir("/proc")? {
+        let path = entry?.path().join("stat");
+        // Processes may be gone by now, and not every entry is a process
+        let Ok(stat) = std::fs::read_to_string(path) else {
+            continue;
+        };
+
+        // The name of the process is in parentheses and may contain any
+        // character, so the fields are read from the closing one:
+        // `pid (comm) state ppid pgrp ...`
+        let Some((_, fields)) = stat.rsplit_once(')') else {
+            continue;
+        };
+        let mut fields = fields.split_whitespace();
+        let state = fields.next();
+        let pgrp = fields.nth(1).and_then(|pgrp| pgrp.parse::<i32>().ok());
+
+        if pgrp == Some(pgid.as_raw()) && !matches!(state, Some("Z" | "X")) {
+            running += 1;
+        }
+    }
+
+    Ok(running)
+}
+
+/// Waits until all processes of the given group are gone
+pub async fn wait_for_group(pgid: Pid) {
+    while group_alive(pgid) {
+        tokio::time::sleep(GROUP_POLL_INTERVAL).await;
+    }
+}
+
+/// Waits until the given process group no longer exists, after which its id
+/// may be reused by an unrelated group. Unlike [`wait_for_group`], this is
+/// meant to watch groups for a long time, and therefore checks less often.
+pub async fn wait_for_group_to_vanish(pgid: Pid) {
+    while group_exists(pgid) {
+        tokio::time::sleep(LEFTOVER_POLL_INTERVAL).await;
+    }
+}
+
+impl FromStr for KillStep {
+    type Err = anyhow::Error;
+
+    /// Parses either a signal (e.g. `SIGINT` or `int`) or a
+    /// duration (e.g. `10s` or `500ms`)
+    fn from_str(s: &str) -> Result<Self> {
+        if s.starts_with(|c: char| c.is_ascii_digit()) {
+            return parse_duration(s).map(KillStep::Wait);
+        }
+
+        parse_signal(s).map(KillStep::Signal)
+    }
+}
+
+/// Parses a signal by its name, with or without the `SIG` prefix
+/// (e.g. `SIGINT` or `int`)
+pub fn parse_signal(s: &str) -> Result<Signal> {
+    let name = s.to_ascii_uppercase();
+    let name = if name.starts_with("SIG") {
+        name
+    } else {
+        format!("SIG{}", name)
+    };
+
+    name.parse()
+        .with_context(|| format!("Unknown signal '{}'", s))
+}
+
+/// Parses durations such as `10s`, `1.5s` or `500ms`. Plain
+/// numbers are milliseconds, like everywhere else.
+fn parse_duration(s: &str) -> Result<Duration> {
+    let (value, unit) = match s.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(idx) => s.split_at(idx),
+        None => (s, "ms"),
+    };
+
+    let value: f64 = value
+        .parse()
+        .with_context(|| format!("Invalid duration '{}'", s))?;
+    let secs = match unit {
+        "ms" => value / 1000.0,
+        "s" => value,
+        "m" => value * 60.0,
+        _ => bail!("Invalid duration '{}'. Supported units are: ms, s, m", s),
+    };
+
+    Ok(Duration::from_secs_f64(secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_step() {
+        assert_eq!(KillStep::Signal(Signal::SIGINT), "SIGINT".parse().unwrap());
+        assert_eq!(KillStep::Signal(Signal::SIGHUP), "hup".parse().unwrap());
+        assert_eq!(
+            KillStep::Wait(Duration::from_secs(10)),
+            "10s".parse().unwrap()
+        );
+        assert_eq!(
+            KillStep::Wait(Duration::from_millis(1500)),
+            "1.5s".parse().unwrap()
+        );
+        assert_eq!(
+            KillStep::Wait(Duration::from_millis(500)),
+            "500".parse().unwrap()
+        );
+        assert!("SIGFOO".parse::<KillStep>().is_err());
+        assert!("10h".parse::<KillStep>().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_signal_group() {
+        use std::os::unix::process::CommandExt;
+
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "sleep 10 & sleep 10"])
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let pgid = Pid::from_raw(child.id() as i32);
+        assert!(group_alive(pgid));
+        assert!(group_exists(pgid));
+
+        signal_group(pgid, Signal::SIGKILL).unwrap();
+        child.wait().unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(!group_alive(pgid));
+    }
+
+    #[test]
+    fn test_sequence() {
+        let signal = KillStep::Signal;
+        let wait = |secs| KillStep::Wait(Duration::from_secs(secs));
+
+        assert_eq!(
+            vec![
+                signal(Signal::SIGINT),
+                wait(10),
+                signal(Signal::SIGTERM),
+                wait(5),
+                signal(Signal::SIGKILL)
+            ],
+            KillSequence::new(Some("SIGINT,10s,SIGTERM,5s,SIGKILL"), None)
+                .unwrap()
+                .steps
+        );
+
+        assert_eq!(
+            vec![signal(Signal::SIGINT), wait(30), signal(Signal::SIGKILL)],
+            KillSequence::new(Some("SIGINT"), Some(Duration::from_secs(30)))
+                .unwrap()
+                .steps
+        );
+
+        assert_eq!(
+            KillSequence::default(),
+            KillSequence::new(None, None).unwrap()
+        );
+        assert_eq!(
+            Some(Duration::from_secs(15)),
+            KillSequence::new(Some("SIGINT,10s,SIGTERM,5s,SIGKILL"), None)
+                .unwrap()
+                .time_to_kill()
+        );
+        assert_eq!(
+            None,
+            KillSequence::new(Some("SIGINT,10s,SIGTERM"), None)
+                .unwrap()
+                .time_to_kill()
+        );
+        assert!(KillSequence::new(Some("5s,SIGKILL"), None).is_err());
+    }
+}
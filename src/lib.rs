@@ -0,0 +1,75 @@
+//! Runs shell commands concurrently, with their output prefixed by the name
+//! of the command they belong to. This is what the `rly` binary is built on,
+//! and can be used from Rust using a [`Runner`].
+
+mod cli;
+mod colors;
+mod command;
+mod command_spec;
+mod config;
+mod config_file;
+mod control;
+mod ctl;
+mod dependencies;
+mod event_loop;
+mod group;
+mod input;
+mod kill;
+mod output;
+mod passthrough;
+mod pty;
+mod readiness;
+mod reaper;
+mod restart;
+mod runner;
+mod signals;
+mod sink;
+mod success;
+mod timings;
+mod tui;
+mod watch;
+
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use clap::{CommandFactory, Parser};
+use command::*;
+use log::debug;
+
+use crate::cli::{Args, SubCommand};
+use crate::config::Config;
+use crate::event_loop::event_loop;
+use crate::output::Output;
+pub use crate::output::Stream;
+pub use crate::runner::{CommandResult, RunResult, Runner};
+pub use crate::sink::{
+    CommandRef, EventKind, FileSink, JsonSink, OutputEvent, OutputSink, RawSink, TerminalSink,
+};
+
+/// Runs `rly` with the arguments it was invoked with, which is all the binary does
+#[doc(hidden)]
+pub async fn run_cli() -> Result<ExitCode> {
+    let mut args: Args = Args::parse();
+    if let Some(SubCommand::Ctl(ctl)) = args.subcommand.take() {
+        return ctl::run(ctl).await;
+    }
+
+    // Only the binary may be the init process, not a program using the library
+    if cfg!(target_os = "linux") && std::process::id() == 1 && !args.no_init {
+        args.init = true;
+    }
+
+    let config = Arc::new(Config::try_from(args)?);
+    debug!("{:#?}", config);
+
+    if config.commands.is_empty() {
+        Args::command().print_long_help()?;
+        println!();
+        bail!("No commands were given");
+    }
+
+    let output = Output::stdio(Arc::clone(&config));
+    let result = event_loop(config, output).await?;
+    Ok(ExitCode::from(result.exit_code as u8))
+}
@@ -0,0 +1,218 @@
+use std::fmt::{Display, Formatter};
+use std::process::ExitStatus;
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use chrono::Local;
+use log::debug;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::colors::colorize;
+use crate::sink::{self, CommandRef, EventKind, OutputEvent, OutputSink};
+use crate::tui::Logs;
+use crate::Config;
+
+/// Format of everything `rly` prints, see [`crate::cli::Args::output_format`]
+/// and [`crate::sink::for_config`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Lines prefixed with the name of the command. This is the default.
+    #[default]
+    Text,
+
+    /// One JSON object per line, see [`JsonEvent`]
+    Jsonl,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            _ => bail!(
+                "Unknown output format '{}'. Possible values are: text, jsonl",
+                s
+            ),
+        }
+    }
+}
+
+/// The stream a line of output was read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Display for Stream {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stream::Stdout => f.write_str("stdout"),
+            Stream::Stderr => f.write_str("stderr"),
+        }
+    }
+}
+
+/// Reports everything the event loop prints to an [`OutputSink`], as text
+/// formatted according to the options of `rly` along with the structured
+/// event. Output of hidden commands is dropped here.
+pub struct Output {
+    config: Arc<Config>,
+    sink: Box<dyn OutputSink>,
+
+    /// Collects everything instead of printing it while the terminal
+    /// UI is shown, see [`crate::cli::Args::tui`]
+    logs: Option<Logs>,
+}
+
+impl Output {
+    pub fn new(config: Arc<Config>, sink: Box<dyn OutputSink>) -> Self {
+        Output {
+            logs: config.tui.then(|| Logs::new(config.commands.len())),
+            config,
+            sink,
+        }
+    }
+
+    /// Prints to stdout and stderr, like the `rly` binary does
+    pub fn stdio(config: Arc<Config>) -> Self {
+        let sink = sink::for_config(&config);
+        Self::new(config, sink)
+    }
+
+    /// Everything collected for the terminal UI
+    pub fn logs(&self) -> Option<&Logs> {
+        self.logs.as_ref()
+    }
+
+    /// Prints everything from here on, once the terminal UI is gone
+    pub fn close_logs(&mut self) {
+        self.logs = None;
+    }
+
+    /// A line of output of the given command
+    pub fn line(&mut self, command_idx: usize, stream: Stream, line: &str) {
+        let cmd = &self.config.commands[command_idx];
+        let text = format!(
+            "{} {}",
+            cmd.prefix(stream),
+            self.colorize(command_idx, stream, line)
+        );
+        self.emit(
+            Some(command_idx),
+            EventKind::Output { stream, line },
+            Some(&text),
+        );
+    }
+
+    pub fn spawned(&mut self, command_idx: usize, is_restart: bool) {
+        let cmd = &self.config.commands[command_idx];
+        if !is_restart {
+            // Initial spawns are not worth mentioning in text
+            self.emit(Some(command_idx), EventKind::Spawn, None);
+            return;
+        }
+
+        let text = format!("{} {} restarted", cmd.prefix(Stream::Stdout), cmd.command);
+        self.emit(Some(command_idx), EventKind::Restart, Some(&text));
+    }
+
+    pub fn exited(&mut self, command_idx: usize, status: &ExitStatus) {
+        let cmd = &self.config.commands[command_idx];
+        let text = format!(
+            "{} {} exited with {}",
+            cmd.prefix(Stream::Stdout),
+            cmd.command,
+            status
+        );
+        self.emit(
+            Some(command_idx),
+            EventKind::Exit { status: *status },
+            Some(&text),
+        );
+    }
+
+    /// A message about the given command, e.g. that it is ready. As text,
+    /// the message is prefixed like the output of the command.
+    pub fn status(&mut self, command_idx: usize, message: &str) {
+        let cmd = &self.config.commands[command_idx];
+        let text = format!("{} {}", cmd.prefix(Stream::Stdout), message);
+        self.emit(
+            Some(command_idx),
+            EventKind::Status { message },
+            Some(&text),
+        );
+    }
+
+    /// A message from `rly` itself, which is never grouped. If it was caused
+    /// by a command, it is hidden along with the output of that command.
+    pub fn notice(&mut self, command_idx: Option<usize>, message: &str) {
+        if command_idx.is_none() && self.config.disable_output() {
+            return;
+        }
+        self.emit(command_idx, EventKind::Notice { message }, Some(message));
+    }
+
+    /// Prints the buffered output of the given command, if output is grouped.
+    /// `is_final` is false if the command is going to be restarted.
+    pub fn finish_group(&mut self, command_idx: usize, is_final: bool) {
+        if let Err(err) = self.sink.finish(command_idx, is_final) {
+            debug!("Failed to print output: {:?}", err);
+        }
+    }
+
+    /// Prints all output that is still buffered
+    pub fn flush(&mut self) -> Result<()> {
+        self.sink.flush()?;
+        Ok(())
+    }
+
+    fn emit(&mut self, command_idx: Option<usize>, kind: EventKind, text: Option<&str>) {
+        if command_idx.is_some_and(|idx| self.config.commands[idx].disable_output()) {
+            return;
+        }
+
+        if let Some(logs) = &mut self.logs {
+            if let Some(text) = text {
+                logs.push(command_idx, text);
+            }
+            return;
+        }
+
+        let config = &self.config;
+        let event = OutputEvent {
+            timestamp: Local::now(),
+            command: command_idx.map(|idx| CommandRef {
+                index: idx,
+                name: &config.names[idx],
+                command: &config.commands[idx].command,
+                pid: config.commands[idx].pid.load(Ordering::Relaxed),
+            }),
+            kind,
+            text,
+        };
+        if let Err(err) = self.sink.event(&event) {
+            debug!("Failed to print output: {:?}", err);
+        }
+    }
+
+    /// Applies the color of the given stream (see [`crate::cli::Args::stream_colors`])
+    fn colorize(&self, command_idx: usize, stream: Stream, line: &str) -> String {
+        if self.config.no_color {
+            return line.to_string();
+        }
+
+        let color = match stream {
+            Stream::Stdout => &self.config.stream_colors[0],
+            Stream::Stderr => &self.config.stream_colors[1],
+        };
+
+        // Colors have been validated when building the config
+        colorize(command_idx, color, line).unwrap_or_else(|_| line.to_string())
+    }
+}
@@ -0,0 +1,114 @@
+/// Replaces the placeholders in the given command with the additional
+/// arguments passed after `--` (see [`crate::cli::Args::passthrough_arguments`]):
+///
+/// - `{1}`, `{2}`, ... are replaced with a single argument
+/// - `{@}` is replaced with all arguments, each quoted separately
+/// - `{*}` is replaced with all arguments, quoted as a single string
+///
+/// Arguments are quoted for the shell, so that e.g. file names containing
+/// spaces are passed as-is. Placeholders referring to arguments that have
+/// not been given are removed, and placeholders can be escaped as `\{1}`.
+pub fn substitute(command: &str, args: &[String]) -> String {
+    let mut result = String::with_capacity(command.len());
+    let mut rest = command;
+
+    while let Some(start) = rest.find('{') {
+        let escaped = rest[..start].ends_with('\\');
+        let Some((placeholder, len)) = placeholder(&rest[start..]) else {
+            result.push_str(&rest[..=start]);
+            rest = &rest[start + 1..];
+            continue;
+        };
+
+        if escaped {
+            result.push_str(&rest[..start - 1]);
+            result.push_str(&rest[start..start + len]);
+        } else {
+            result.push_str(&rest[..start]);
+            result.push_str(&replacement(placeholder, args));
+        }
+        rest = &rest[start + len..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// Returns the contents and length of the placeholder at the start of `input`
+fn placeholder(input: &str) -> Option<(&str, usize)> {
+    let end = input.find('}')?;
+    let placeholder = &input[1..end];
+
+    let valid = matches!(placeholder, "@" | "*")
+        || (placeholder.starts_with(|c: char| matches!(c, '1'..='9'))
+            && placeholder.chars().all(|c| c.is_ascii_digit()));
+
+    valid.then_some((placeholder, end + 1))
+}
+
+fn replacement(placeholder: &str, args: &[String]) -> String {
+    match placeholder {
+        "@" => args
+            .iter()
+            .map(|arg| quote(arg))
+            .collect::<Vec<_>>()
+            .join(" "),
+        "*" => quote(&args.join(" ")),
+        idx => idx
+            .parse::<usize>()
+            .ok()
+            .and_then(|idx| args.get(idx - 1))
+            .map(|arg| quote(arg))
+            .unwrap_or_default(),
+    }
+}
+
+/// Quotes the given argument for `sh`, unless it only consists of
+/// characters that don't have a special meaning to the shell.
+fn quote(arg: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_-./=:,@%+".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        return arg.to_string();
+    }
+
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args() -> Vec<String> {
+        ["src/foo.ts", "my file.ts", "it's"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_substitute() {
+        let args = args();
+        assert_eq!("jest src/foo.ts", substitute("jest {1}", &args));
+        assert_eq!(
+            r"eslint src/foo.ts 'my file.ts' 'it'\''s'",
+            substitute("eslint {@}", &args)
+        );
+        assert_eq!(
+            r"echo 'src/foo.ts my file.ts it'\''s'",
+            substitute("echo {*}", &args)
+        );
+        assert_eq!("echo  done", substitute("echo {4} done", &args));
+        assert_eq!("echo ''", substitute("echo {*}", &[]));
+    }
+
+    #[test]
+    fn test_leaves_other_braces_alone() {
+        let args = args();
+        assert_eq!("echo {1}", substitute(r"echo \{1}", &args));
+        assert_eq!("echo {0} {x} {", substitute("echo {0} {x} {", &args));
+        assert_eq!(
+            "awk '{print $1}' src/foo.ts",
+            substitute("awk '{print $1}' {1}", &args)
+        );
+    }
+}
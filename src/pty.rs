@@ -0,0 +1,201 @@
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+use std::pin::Pin;
+use std::process::Stdio;
+use std::task::{ready, Context as TaskContext, Poll};
+
+use anyhow::{Context, Result};
+use log::debug;
+use nix::fcntl::{fcntl, FcntlArg, FdFlag, OFlag};
+use nix::libc;
+use nix::pty::{openpty, Winsize};
+use nix::sys::termios::{tcgetattr, tcsetattr, LocalFlags, SetArg};
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::process::Command as TokioCommand;
+use tokio::signal::unix::{signal, SignalKind};
+
+/// Size of the pseudo-terminal if `rly` itself is not attached to a terminal
+const DEFAULT_SIZE: (u16, u16) = (24, 80);
+
+nix::ioctl_read_bad!(get_window_size, libc::TIOCGWINSZ, Winsize);
+nix::ioctl_write_ptr_bad!(set_window_size, libc::TIOCSWINSZ, Winsize);
+
+/// The controlling side of a pseudo-terminal that a command is attached to,
+/// see [`crate::cli::Args::pty`]
+pub struct Pty {
+    master: OwnedFd,
+}
+
+impl Pty {
+    /// Opens a pseudo-terminal with the size of the terminal of `rly`, and
+    /// attaches the given command to it. The command becomes the leader of
+    /// a new session (and thereby process group), with the pseudo-terminal
+    /// as its controlling terminal.
+    pub fn attach(command: &mut TokioCommand) -> Result<Self> {
+        let pty = openpty(&terminal_size(), None).context("Failed to open pseudo-terminal")?;
+
+        // Neither side may be inherited by other commands. The command gets the
+        // slave as its standard streams, which are not closed on exec.
+        for fd in [&pty.master, &pty.slave] {
+            fcntl(fd, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))?;
+        }
+
+        // Input forwarded by `rly` has already been echoed by the terminal it was typed into
+        let mut termios = tcgetattr(&pty.slave)?;
+        termios.local_flags.remove(LocalFlags::ECHO);
+        tcsetattr(&pty.slave, SetArg::TCSANOW, &termios)?;
+
+        command
+            .stdin(Stdio::from(pty.slave.try_clone()?))
+            .stdout(Stdio::from(pty.slave.try_clone()?))
+            .stderr(Stdio::from(pty.slave));
+
+        // SAFETY: Only async-signal-safe functions are called between fork and exec
+        unsafe {
+            command.pre_exec(|| {
+                nix::unistd::setsid()?;
+                if libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        Ok(Pty { master: pty.master })
+    }
+
+    /// Returns a stream to read everything the command writes to the terminal,
+    /// and to type into the terminal (see [`crate::cli::Args::handle_input`])
+    pub fn stream(&self) -> Result<PtyStream> {
+        let fd = self.master.try_clone()?;
+        fcntl(&fd, FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?;
+        Ok(PtyStream {
+            fd: AsyncFd::new(fd)?,
+        })
+    }
+
+    /// Resizes the pseudo-terminal whenever the terminal of `rly` is resized,
+    /// until the returned future is dropped
+    pub fn forward_resizes(&self) -> Result<impl std::future::Future<Output = ()>> {
+        let master = self.master.try_clone()?;
+        let mut resized = signal(SignalKind::window_change())?;
+
+        Ok(async move {
+            while resized.recv().await.is_some() {
+                let size = terminal_size();
+                // SAFETY: `size` is a valid winsize for the duration of the call
+                if let Err(err) = unsafe { set_window_size(master.as_raw_fd(), &size) } {
+                    debug!("Failed to resize pseudo-terminal: {err}");
+                }
+            }
+        })
+    }
+}
+
+/// The controlling side of a [`Pty`]. Once the command and all of its children
+/// closed the terminal, reading from it behaves like the end of a file.
+pub struct PtyStream {
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl AsyncRead for PtyStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            let mut guard = ready!(self.fd.poll_read_ready(cx))?;
+            let unfilled = buf.initialize_unfilled();
+
+            match guard.try_io(|fd| {
+                nix::unistd::read(fd.get_ref().as_fd(), unfilled).map_err(io::Error::from)
+            }) {
+                Ok(Ok(len)) => {
+                    buf.advance(len);
+                    return Poll::Ready(Ok(()));
+                }
+                // Linux reports a closed terminal as an error instead of EOF
+                Ok(Err(err)) if err.raw_os_error() == Some(libc::EIO) => {
+                    return Poll::Ready(Ok(()));
+                }
+                Ok(Err(err)) => return Poll::Ready(Err(err)),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+impl AsyncWrite for PtyStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            let mut guard = ready!(self.fd.poll_write_ready(cx))?;
+
+            match guard
+                .try_io(|fd| nix::unistd::write(fd.get_ref().as_fd(), buf).map_err(io::Error::from))
+            {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Returns what a terminal would end up showing for the given line. Terminals
+/// translate `\n` into `\r\n`, and progress bars redraw a line by going back to
+/// its start using `\r`, so only the text after the last `\r` is kept.
+pub fn visible_line(line: &str) -> &str {
+    let line = line.trim_end_matches('\r');
+    line.rsplit('\r').next().unwrap_or(line)
+}
+
+fn terminal_size() -> Winsize {
+    let (rows, cols) = DEFAULT_SIZE;
+    let mut size = Winsize {
+        ws_row: rows,
+        ws_col: cols,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+
+    // stdout may be piped to e.g. `tee` while stderr is still a terminal
+    for fd in [io::stdout().as_raw_fd(), io::stderr().as_raw_fd()] {
+        let mut actual = size;
+        // SAFETY: `actual` is a valid winsize for the duration of the call
+        if unsafe { get_window_size(fd, &mut actual) }.is_ok() && actual.ws_col > 0 {
+            size = actual;
+            break;
+        }
+    }
+
+    size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_visible_line() {
+        assert_eq!("done", visible_line("done\r"));
+        assert_eq!(
+            "[====] 100%",
+            visible_line("[=   ] 25%\r[==  ] 50%\r[====] 100%\r")
+        );
+        assert_eq!("", visible_line(""));
+        assert_eq!("no carriage return", visible_line("no carriage return"));
+    }
+}
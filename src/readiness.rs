@@ -0,0 +1,230 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use regex::Regex;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::oneshot;
+
+use crate::command_spec::CommandSpec;
+
+/// How long to wait for a command to become ready, if not configured otherwise
+const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often the TCP, HTTP and file checks are retried
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long a single TCP or HTTP check may take. Servers that accept
+/// connections but don't respond in time are not ready yet.
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Checks that have to pass before a command is considered to be ready, as
+/// opposed to merely started. If multiple checks are configured, all of them
+/// have to pass.
+#[derive(Debug)]
+pub struct Readiness {
+    /// A line of output has to match this pattern
+    pub log: Option<Regex>,
+
+    /// A TCP connection to this port on localhost has to succeed
+    pub port: Option<u16>,
+
+    /// A GET request to this URL has to return status 200
+    pub http: Option<HttpCheck>,
+
+    /// This file has to exist
+    pub file: Option<PathBuf>,
+
+    /// How long to wait for the checks to pass
+    pub timeout: Duration,
+}
+
+#[derive(Debug)]
+pub struct HttpCheck {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl Readiness {
+    /// Returns the readiness checks configured for the given command, if any.
+    /// Relative paths for file checks are resolved against `cwd`.
+    pub fn from_spec(spec: &CommandSpec, cwd: Option<&Path>) -> Result<Option<Self>> {
+        let log = spec
+            .ready_log
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .context("Invalid pattern for ready-log")?;
+
+        let http = spec
+            .ready_http
+            .as_deref()
+            .map(HttpCheck::parse)
+            .transpose()?;
+
+        let file = spec.ready_file.as_ref().map(|file| match cwd {
+            Some(cwd) => cwd.join(file),
+            None => file.clone(),
+        });
+
+        if log.is_none() && spec.ready_port.is_none() && http.is_none() && file.is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(Readiness {
+            log,
+            port: spec.ready_port,
+            http,
+            file,
+            timeout: spec
+                .ready_timeout
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_READY_TIMEOUT),
+        }))
+    }
+
+    /// Waits until all checks pass. `log_matched` resolves once a line of output
+    /// matched [`Readiness::log`]. Returns the time it took for the command to
+    /// become ready, or `None` if it didn't become ready within the timeout.
+    pub async fn wait(&self, log_matched: Option<oneshot::Receiver<()>>) -> Option<Duration> {
+        let started = Instant::now();
+
+        let checks = async {
+            if let Some(log_matched) = log_matched {
+                // If the sender is dropped, the process exited and this task
+                // will be aborted anyway.
+                log_matched.await.unwrap_or(());
+            }
+
+            while !self.poll().await {
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        };
+
+        tokio::time::timeout(self.timeout, checks)
+            .await
+            .ok()
+            .map(|_| started.elapsed())
+    }
+
+    async fn poll(&self) -> bool {
+        if let Some(port) = self.port {
+            let connect = TcpStream::connect(("localhost", port));
+            match tokio::time::timeout(ATTEMPT_TIMEOUT, connect).await {
+                Ok(Ok(_)) => {}
+                _ => return false,
+            }
+        }
+
+        if let Some(http) = &self.http {
+            if !http.is_ok().await {
+                return false;
+            }
+        }
+
+        if let Some(file) = &self.file {
+            if !file.exists() {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+impl HttpCheck {
+    /// Parses URLs of the form `http://host[:port][/path]`
+    fn parse(url: &str) -> Result<Self> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| anyhow!("Only http:// URLs are supported for ready-http: {}", url))?;
+
+        let (authority, path) = match rest.find('/') {
+            Some(idx) => (&rest[..idx], &rest[idx..]),
+            None => (rest, "/"),
+        };
+
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .with_context(|| format!("Invalid port in ready-http URL: {}", url))?,
+            ),
+            None => (authority, 80),
+        };
+
+        if host.is_empty() {
+            bail!("Missing host in ready-http URL: {}", url);
+        }
+
+        Ok(HttpCheck {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    async fn is_ok(&self) -> bool {
+        matches!(
+            tokio::time::timeout(ATTEMPT_TIMEOUT, self.request()).await,
+            Ok(Ok(true))
+        )
+    }
+
+    async fn request(&self) -> Result<bool> {
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+        let request = format!(
+            "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+            self.path, self.host
+        );
+        stream.write_all(request.as_bytes()).await?;
+
+        // We only care about the status line, e.g. "HTTP/1.1 200 OK"
+        let mut buf = [0; 32];
+        let mut len = 0;
+        while len < buf.len() {
+            let read = stream.read(&mut buf[len..]).await?;
+            if read == 0 {
+                break;
+            }
+            len += read;
+        }
+
+        let status_line = String::from_utf8_lossy(&buf[..len]);
+        Ok(status_line.split_whitespace().nth(1) == Some("200"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_http_check() {
+        let check = HttpCheck::parse("http://localhost:8080/health").unwrap();
+        assert_eq!("localhost", check.host);
+        assert_eq!(8080, check.port);
+        assert_eq!("/health", check.path);
+
+        let check = HttpCheck::parse("http://127.0.0.1").unwrap();
+        assert_eq!(80, check.port);
+        assert_eq!("/", check.path);
+
+        assert!(HttpCheck::parse("https://localhost").is_err());
+        assert!(HttpCheck::parse("http://:80/").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_http_check_times_out() {
+        // Connections are accepted by the OS, but never responded to
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let check = HttpCheck::parse(&format!("http://127.0.0.1:{}/", port)).unwrap();
+
+        let started = Instant::now();
+        assert!(!check.is_ok().await);
+        assert!(started.elapsed() < ATTEMPT_TIMEOUT * 2);
+    }
+}
@@ -0,0 +1,119 @@
+use std::io;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use tokio::process::{Child, Command as TokioCommand};
+
+/// How often orphans are reaped besides whenever `SIGCHLD` arrives. Reaping
+/// pauses while a command's own exit status is pending, and signals that
+/// arrive in the meantime are merged into one.
+#[cfg(target_os = "linux")]
+const REAP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// PIDs of the processes spawned by `rly` itself. Their exit statuses are
+/// collected by tokio, so the reaper has to leave them alone.
+static SPAWNED: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+fn spawned() -> MutexGuard<'static, Vec<u32>> {
+    SPAWNED.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Spawns a process and registers it as one of the processes spawned by `rly`.
+/// The lock is held while spawning, so that the reaper cannot mistake a
+/// process that exits right away for an orphan.
+pub fn spawn(command: &mut TokioCommand) -> io::Result<Child> {
+    let mut spawned = spawned();
+    let child = command.spawn()?;
+    if let Some(pid) = child.id() {
+        spawned.push(pid);
+    }
+
+    Ok(child)
+}
+
+/// Forgets a process registered by [`spawn`], once its exit status has been collected
+pub fn forget(pid: u32) {
+    spawned().retain(|spawned| *spawned != pid);
+}
+
+/// Acts as the init process until dropped, see [`start`]
+pub struct Reaper {
+    task: tokio::task::AbortHandle,
+}
+
+/// Makes `rly` act as the init process, see [`crate::cli::Args::init`]. Processes
+/// left behind by commands are adopted by `rly`, and reaped once they exited,
+/// along with any other orphans when running as PID 1.
+#[cfg(target_os = "linux")]
+pub fn start() -> anyhow::Result<Reaper> {
+    use anyhow::Context;
+    use tokio::signal::unix::{signal, SignalKind};
+
+    nix::sys::prctl::set_child_subreaper(true).context("Unable to become a subreaper")?;
+    let mut sigchld = signal(SignalKind::child()).context("Unable to listen to SIGCHLD")?;
+
+    let task = tokio::spawn(async move {
+        loop {
+            reap_orphans();
+            tokio::select! {
+                _ = sigchld.recv() => {}
+                _ = tokio::time::sleep(REAP_INTERVAL) => {}
+            }
+        }
+    });
+
+    Ok(Reaper {
+        task: task.abort_handle(),
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn start() -> anyhow::Result<Reaper> {
+    anyhow::bail!("--init is only supported on Linux")
+}
+
+impl Drop for Reaper {
+    /// Leaves the exit statuses of children to the program again, which matters
+    /// when `rly` is used as a library. Processes adopted in the meantime
+    /// remain children of the program.
+    fn drop(&mut self) {
+        self.task.abort();
+
+        #[cfg(target_os = "linux")]
+        if let Err(err) = nix::sys::prctl::set_child_subreaper(false) {
+            log::debug!("Failed to stop being a subreaper: {err}");
+        }
+    }
+}
+
+/// Reaps the children of `rly` that exited and were not spawned by it. This is
+/// what `waitpid(-1, ...)` does, except that exited children are only peeked at
+/// first (`WNOWAIT`), so that no exit status is taken away from tokio.
+#[cfg(target_os = "linux")]
+fn reap_orphans() {
+    use log::debug;
+    use nix::sys::wait::{waitid, waitpid, Id, WaitPidFlag};
+
+    let spawned = spawned();
+    loop {
+        let flags = WaitPidFlag::WEXITED | WaitPidFlag::WNOHANG | WaitPidFlag::WNOWAIT;
+        let Ok(status) = waitid(Id::All, flags) else {
+            // No children at all
+            break;
+        };
+
+        match status.pid() {
+            Some(pid) if !spawned.contains(&(pid.as_raw() as u32)) => {
+                match waitpid(pid, Some(WaitPidFlag::WNOHANG)) {
+                    Ok(status) => debug!("Reaped orphaned process {pid}: {status:?}"),
+                    Err(err) => debug!("Failed to reap orphaned process {pid}: {err}"),
+                }
+            }
+
+            // Either no child exited, or the next one is a command whose exit
+            // status is collected by tokio. The remaining ones are reaped
+            // the next time around.
+            _ => break,
+        }
+    }
+}
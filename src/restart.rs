@@ -0,0 +1,292 @@
+use std::collections::VecDeque;
+use std::process::ExitStatus;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+
+/// When processes are restarted, see [`crate::cli::Args::restart_policy`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RestartPolicy {
+    /// Restart processes that exited with a non-zero code. This is the default.
+    #[default]
+    OnFailure,
+
+    /// Restart processes whenever they exit
+    Always,
+
+    Never,
+
+    /// Like [`RestartPolicy::Always`], except for processes that have been
+    /// stopped deliberately by terminating them with a signal
+    UnlessStopped,
+}
+
+impl FromStr for RestartPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "on-failure" => Ok(RestartPolicy::OnFailure),
+            "always" => Ok(RestartPolicy::Always),
+            "never" => Ok(RestartPolicy::Never),
+            "unless-stopped" => Ok(RestartPolicy::UnlessStopped),
+            _ => bail!(
+                "Unknown restart policy '{}'. Possible values are: on-failure, always, never, \
+                 unless-stopped",
+                s
+            ),
+        }
+    }
+}
+
+/// Decides whether and when a command is restarted once it exited
+#[derive(Debug, Clone, PartialEq)]
+pub struct Restart {
+    /// See [`crate::cli::Args::restart_policy`]
+    pub policy: RestartPolicy,
+
+    /// See [`crate::cli::Args::restart_tries`]. Negative numbers
+    /// restart the command indefinitely.
+    pub tries: i32,
+
+    /// See [`crate::cli::Args::restart_after`]
+    pub delay: Duration,
+
+    /// See [`crate::cli::Args::restart_backoff`]
+    pub backoff: f64,
+
+    /// See [`crate::cli::Args::restart_max_delay`]
+    pub max_delay: Option<Duration>,
+
+    /// See [`crate::cli::Args::restart_jitter`]
+    pub jitter: f64,
+
+    /// See [`crate::cli::Args::restart_reset_after`]
+    pub reset_after: Option<Duration>,
+
+    /// See [`crate::cli::Args::crash_loop_failures`]
+    pub crash_loop: Option<CrashLoop>,
+}
+
+/// A command that failed this many times within the window is in a crash
+/// loop, and is not restarted anymore. Every exit counts as a failure, unless
+/// the process stayed up for [`Restart::reset_after`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CrashLoop {
+    pub failures: usize,
+    pub window: Duration,
+}
+
+/// The recent failures of a command, to detect a [`CrashLoop`]
+#[derive(Debug, Default)]
+pub struct FailureHistory {
+    failures: VecDeque<Instant>,
+}
+
+impl FailureHistory {
+    /// Records a failure at the given time, and returns whether
+    /// the command is in a crash loop
+    pub fn record(&mut self, at: Instant, crash_loop: &CrashLoop) -> bool {
+        self.failures.push_back(at);
+        while self
+            .failures
+            .front()
+            .is_some_and(|failure| at.duration_since(*failure) > crash_loop.window)
+        {
+            self.failures.pop_front();
+        }
+
+        self.failures.len() >= crash_loop.failures
+    }
+}
+
+impl Default for Restart {
+    fn default() -> Self {
+        Restart {
+            policy: RestartPolicy::default(),
+            tries: 0,
+            delay: Duration::ZERO,
+            backoff: 1.0,
+            max_delay: None,
+            jitter: 0.0,
+            reset_after: None,
+            crash_loop: None,
+        }
+    }
+}
+
+impl Restart {
+    /// Whether a process that exited with the given status is restarted,
+    /// given how often it has been restarted (since the last reset)
+    pub fn should_restart(&self, status: &ExitStatus, restarts: u32) -> bool {
+        let policy_applies = match self.policy {
+            RestartPolicy::OnFailure => !status.success(),
+            RestartPolicy::Always => true,
+            RestartPolicy::Never => false,
+            RestartPolicy::UnlessStopped => !stopped(status),
+        };
+
+        policy_applies && (self.tries < 0 || (restarts as i64) < self.tries as i64)
+    }
+
+    /// Whether a process that stayed up for the given time counts as healthy,
+    /// meaning that its restart tries and backoff start over
+    pub fn should_reset(&self, uptime: Duration) -> bool {
+        self.reset_after
+            .is_some_and(|reset_after| uptime >= reset_after)
+    }
+
+    /// The delay before the given restart (starting at zero), including backoff and jitter
+    pub fn delay(&self, restarts: u32) -> Duration {
+        let mut delay = self.delay.as_secs_f64() * self.backoff.powi(restarts as i32);
+        if let Some(max_delay) = self.max_delay {
+            delay = delay.min(max_delay.as_secs_f64());
+        }
+
+        if self.jitter > 0.0 {
+            delay *= 1.0 + self.jitter * (fastrand::f64() * 2.0 - 1.0);
+        }
+
+        // Without a maximum, the delay may grow beyond what fits into a Duration
+        match Duration::try_from_secs_f64(delay) {
+            Ok(delay) => delay,
+            Err(_) if delay > 0.0 => Duration::MAX,
+            Err(_) => Duration::ZERO,
+        }
+    }
+}
+
+/// Whether the process has been terminated by a signal that is typically
+/// sent to stop it, as opposed to e.g. crashing with `SIGSEGV`
+fn stopped(status: &ExitStatus) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        const STOP_SIGNALS: [i32; 4] = [
+            nix::libc::SIGINT,
+            nix::libc::SIGTERM,
+            nix::libc::SIGKILL,
+            nix::libc::SIGHUP,
+        ];
+        status
+            .signal()
+            .is_some_and(|signal| STOP_SIGNALS.contains(&signal))
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = status;
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn test_parse_policy() {
+        assert_eq!(RestartPolicy::Always, "always".parse().unwrap());
+        assert_eq!(
+            RestartPolicy::UnlessStopped,
+            "unless-stopped".parse().unwrap()
+        );
+        assert!("sometimes".parse::<RestartPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_backoff() {
+        let restart = Restart {
+            delay: millis(100),
+            backoff: 2.0,
+            max_delay: Some(millis(1000)),
+            ..Default::default()
+        };
+
+        assert_eq!(millis(100), restart.delay(0));
+        assert_eq!(millis(200), restart.delay(1));
+        assert_eq!(millis(800), restart.delay(3));
+        assert_eq!(millis(1000), restart.delay(4));
+        assert_eq!(millis(1000), restart.delay(100));
+
+        let unbounded = Restart {
+            max_delay: None,
+            ..restart
+        };
+        assert_eq!(Duration::MAX, unbounded.delay(10_000));
+    }
+
+    #[test]
+    fn test_jitter() {
+        let restart = Restart {
+            delay: millis(1000),
+            jitter: 0.1,
+            ..Default::default()
+        };
+
+        for _ in 0..100 {
+            let delay = restart.delay(0);
+            assert!(delay >= millis(900) && delay <= millis(1100), "{:?}", delay);
+        }
+    }
+
+    #[test]
+    fn test_reset() {
+        let restart = Restart {
+            reset_after: Some(millis(500)),
+            ..Default::default()
+        };
+
+        assert!(!restart.should_reset(millis(499)));
+        assert!(restart.should_reset(millis(500)));
+        assert!(!Restart::default().should_reset(Duration::MAX));
+    }
+
+    #[test]
+    fn test_crash_loop() {
+        let crash_loop = CrashLoop {
+            failures: 3,
+            window: Duration::from_secs(10),
+        };
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        let mut history = FailureHistory::default();
+        assert!(!history.record(at(0), &crash_loop));
+        assert!(!history.record(at(5), &crash_loop));
+        // The first failure is out of the window by now
+        assert!(!history.record(at(11), &crash_loop));
+        assert!(history.record(at(12), &crash_loop));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_should_restart() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let success = ExitStatus::from_raw(0);
+        let failure = ExitStatus::from_raw(1 << 8);
+        let terminated = ExitStatus::from_raw(nix::libc::SIGTERM);
+        let crashed = ExitStatus::from_raw(nix::libc::SIGSEGV);
+
+        let restart = |policy, tries| Restart {
+            policy,
+            tries,
+            ..Default::default()
+        };
+
+        assert!(!restart(RestartPolicy::OnFailure, 1).should_restart(&success, 0));
+        assert!(restart(RestartPolicy::OnFailure, 1).should_restart(&failure, 0));
+        assert!(!restart(RestartPolicy::OnFailure, 1).should_restart(&failure, 1));
+        assert!(restart(RestartPolicy::Always, -1).should_restart(&success, 1000));
+        assert!(!restart(RestartPolicy::Never, -1).should_restart(&failure, 0));
+        assert!(!restart(RestartPolicy::UnlessStopped, -1).should_restart(&terminated, 0));
+        assert!(restart(RestartPolicy::UnlessStopped, -1).should_restart(&crashed, 0));
+        assert!(restart(RestartPolicy::UnlessStopped, -1).should_restart(&success, 0));
+    }
+}
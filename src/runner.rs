@@ -0,0 +1,196 @@
+use std::io::Write;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+
+use crate::command_spec::CommandSpec;
+use crate::config_file::ConfigFile;
+use crate::event_loop::event_loop;
+use crate::output::Output;
+use crate::sink::{OutputSink, TerminalSink};
+use crate::{Args, Config};
+
+/// Runs commands concurrently, like the `rly` binary does. Options that are
+/// not set behave like their defaults on the command line, except that no
+/// config file is read, nothing is printed unless [`Runner::output`] (or
+/// [`Runner::sink`]) is given, and signals are left to the program unless
+/// [`Runner::handle_signals`] is enabled.
+///
+/// ```no_run
+/// # async fn run() -> anyhow::Result<()> {
+/// let result = rly::Runner::new()
+///     .named("api", "cargo run")
+///     .named("web", "npm start")
+///     .kill_others(true)
+///     .output(std::io::stdout())
+///     .run()
+///     .await?;
+///
+/// for command in &result.commands {
+///     println!("{} exited with {:?}", command.name, command.exit_code);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct Runner {
+    args: Args,
+    commands: Vec<CommandSpec>,
+    sink: Option<Box<dyn OutputSink>>,
+    handle_signals: bool,
+}
+
+/// What [`Runner::run`] returns once all commands exited
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunResult {
+    /// The exit code the `rly` binary would exit with, see [`Runner::success`]
+    pub exit_code: i32,
+
+    /// The result of every command, in the order they were added
+    pub commands: Vec<CommandResult>,
+}
+
+impl RunResult {
+    /// Whether the commands succeeded, see [`Runner::success`]
+    pub fn success(&self) -> bool {
+        self.exit_code == 0
+    }
+}
+
+/// How a single command of a [`Runner`] ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandResult {
+    pub name: String,
+    pub command: String,
+
+    /// Exit code of the last process of the command. `None` if the command
+    /// was never run, e.g. because `rly` was interrupted before.
+    pub exit_code: Option<i32>,
+}
+
+impl Runner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a shell command, which is named by its index
+    pub fn command(mut self, command: impl Into<String>) -> Self {
+        self.commands.push(CommandSpec {
+            command: command.into(),
+            ..Default::default()
+        });
+        self
+    }
+
+    /// Adds a shell command with the given name, which its output is prefixed with
+    pub fn named(mut self, name: impl Into<String>, command: impl Into<String>) -> Self {
+        self.commands.push(CommandSpec {
+            command: command.into(),
+            name: Some(name.into()),
+            ..Default::default()
+        });
+        self
+    }
+
+    /// Where the prefixed output of all commands (stdout and stderr) and the
+    /// messages of `rly` are written to, using a [`TerminalSink`]
+    pub fn output(self, output: impl Write + Send + 'static) -> Self {
+        self.sink(TerminalSink::with_writer(output))
+    }
+
+    /// Receives everything that happens while running the commands, e.g. a
+    /// [`crate::JsonSink`] or a sink of your own
+    pub fn sink(mut self, sink: impl OutputSink + 'static) -> Self {
+        self.sink = Some(Box::new(sink));
+        self
+    }
+
+    /// Terminates all other commands once a command exited, like `--kill-others`
+    pub fn kill_others(mut self, kill_others: bool) -> Self {
+        self.args.kill_others = kill_others;
+        self
+    }
+
+    /// Terminates all other commands once a command failed, like `--kill-others-on-fail`
+    pub fn kill_others_on_fail(mut self, kill_others_on_fail: bool) -> Self {
+        self.args.kill_others_on_fail = kill_others_on_fail;
+        self
+    }
+
+    /// How often a failed command is restarted, like `--restart-tries`.
+    /// Negative numbers restart it forever.
+    pub fn restart_tries(mut self, tries: i32) -> Self {
+        self.args.restart_tries = Some(tries);
+        self
+    }
+
+    /// How long to wait before restarting a command, like `--restart-after`
+    pub fn restart_after(mut self, delay: Duration) -> Self {
+        self.args.restart_after = Some(delay.as_millis() as u64);
+        self
+    }
+
+    /// Which commands have to succeed for the run to succeed, like `--success`,
+    /// e.g. `all` (the default), `first`, `last` or `command-api`
+    pub fn success(mut self, condition: impl Into<String>) -> Self {
+        self.args.success = Some(condition.into());
+        self
+    }
+
+    /// The prefix of every line of output, like `--prefix`, e.g. `[{name}]`
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.args.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Disables colors in the output, like `--no-color`
+    pub fn no_color(mut self, no_color: bool) -> Self {
+        self.args.no_color = no_color;
+        self
+    }
+
+    /// Terminates the commands on Ctrl-C and `SIGTERM`, and forwards `SIGHUP`,
+    /// `SIGUSR1`, `SIGUSR2` and `SIGQUIT` to them, like the `rly` binary does.
+    ///
+    /// This affects the whole program, permanently: once tokio handles these
+    /// signals, their default handling is never restored. Even after
+    /// [`Runner::run`] returned, Ctrl-C no longer terminates the program.
+    pub fn handle_signals(mut self, handle_signals: bool) -> Self {
+        self.handle_signals = handle_signals;
+        self
+    }
+
+    /// Acts as the init process, like `--init`. Until [`Runner::run`] returns,
+    /// this reaps every child process of the program that was not started by
+    /// the runner, so the program must not wait for its own in the meantime.
+    /// Unlike the `rly` binary, this is not enabled automatically when running
+    /// as PID 1.
+    pub fn init(mut self, init: bool) -> Self {
+        self.args.init = init;
+        self
+    }
+
+    /// Runs all commands until they exited, or until they are interrupted
+    /// (e.g. by Ctrl-C, see [`Runner::handle_signals`]), in which case they
+    /// are terminated first
+    pub async fn run(self) -> Result<RunResult> {
+        if self.commands.is_empty() {
+            bail!("No commands were given");
+        }
+
+        let file = ConfigFile {
+            commands: self.commands,
+            ..Default::default()
+        };
+        let mut config = Config::new(self.args, file)?;
+        config.handle_signals = self.handle_signals;
+        let config = Arc::new(config);
+        let sink = self
+            .sink
+            .unwrap_or_else(|| Box::new(TerminalSink::with_writer(std::io::sink())));
+        let output = Output::new(Arc::clone(&config), sink);
+
+        event_loop(config, output).await
+    }
+}
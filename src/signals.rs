@@ -0,0 +1,48 @@
+use anyhow::{Context, Result};
+use nix::sys::signal::Signal;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc;
+
+/// Signals received by `rly` that are forwarded to the running commands,
+/// see [`crate::cli::Args::signal_targets`]
+pub const FORWARDED_SIGNALS: [Signal; 4] = [
+    Signal::SIGHUP,
+    Signal::SIGUSR1,
+    Signal::SIGUSR2,
+    Signal::SIGQUIT,
+];
+
+/// Listens for the signals that `rly` handles, which are `SIGINT` (Ctrl-C),
+/// `SIGTERM` (shutting down like Ctrl-C) and the [`FORWARDED_SIGNALS`].
+///
+/// Handling these matters when `rly` runs as the entrypoint of a container
+/// or as a service, where it is stopped and reloaded using signals.
+pub fn listen() -> Result<mpsc::Receiver<Signal>> {
+    let (tx, rx) = mpsc::channel(FORWARDED_SIGNALS.len() + 2);
+
+    for kind in [Signal::SIGINT, Signal::SIGTERM]
+        .into_iter()
+        .chain(FORWARDED_SIGNALS)
+    {
+        let mut stream = signal(SignalKind::from_raw(kind as i32))
+            .with_context(|| format!("Unable to listen to {}", kind))?;
+
+        // Stops listening once the receiver is dropped. The handlers installed
+        // by tokio stay in place though, see [`crate::Runner::handle_signals`].
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    received = stream.recv() => {
+                        if received.is_none() || tx.send(kind).await.is_err() {
+                            break;
+                        }
+                    }
+                    _ = tx.closed() => break,
+                }
+            }
+        });
+    }
+
+    Ok(rx)
+}
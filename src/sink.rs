@@ -0,0 +1,561 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::ExitStatus;
+
+use chrono::{DateTime, Local, SecondsFormat};
+use serde_derive::Serialize;
+
+use crate::group::OutputGroups;
+use crate::output::{OutputFormat, Stream};
+use crate::Config;
+
+/// Where a sink writes to, e.g. stdout
+type Writer = Box<dyn Write + Send>;
+
+/// Receives everything `rly` reports while running commands: their output
+/// and what happens to them. Output of hidden commands (see `--hide`) never
+/// reaches a sink.
+///
+/// The built-in sinks are [`TerminalSink`], [`RawSink`], [`JsonSink`] and
+/// [`FileSink`]. Sinks are called from the event loop, so handling an event
+/// must not block for long.
+pub trait OutputSink: Send {
+    fn event(&mut self, event: &OutputEvent) -> io::Result<()>;
+
+    /// The given command won't produce any more output, unless `is_final` is
+    /// false, in which case it is about to be restarted. Sinks that group
+    /// output by command (see `--group`) print it here.
+    fn finish(&mut self, command_idx: usize, is_final: bool) -> io::Result<()> {
+        let _ = (command_idx, is_final);
+        Ok(())
+    }
+
+    /// Called once all commands exited, after which no more events are sent
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Something reported to an [`OutputSink`]
+#[derive(Debug, Clone)]
+pub struct OutputEvent<'a> {
+    pub timestamp: DateTime<Local>,
+
+    /// The command the event is about. Only notices may be about no command.
+    pub command: Option<CommandRef<'a>>,
+
+    pub kind: EventKind<'a>,
+
+    /// The event as printed by [`TerminalSink`], prefixed (and colored) as
+    /// configured. `None` for events that are not printed as text, like the
+    /// first spawn of a command.
+    pub text: Option<&'a str>,
+}
+
+/// The command an [`OutputEvent`] is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandRef<'a> {
+    pub index: usize,
+    pub name: &'a str,
+    pub command: &'a str,
+
+    /// PID of the current process of the command, or zero if it never ran
+    pub pid: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventKind<'a> {
+    /// The command has been started for the first time
+    Spawn,
+
+    /// The command has been started again, e.g. after it failed
+    Restart,
+
+    /// A line of output of the command
+    Output { stream: Stream, line: &'a str },
+
+    /// The process of the command exited, and may be restarted
+    Exit { status: ExitStatus },
+
+    /// A message about the command, e.g. that it is ready
+    Status { message: &'a str },
+
+    /// A message from `rly` itself, e.g. that it is terminating all
+    /// processes. It may have been caused by a command.
+    Notice { message: &'a str },
+}
+
+/// Picks the built-in sink for the options of `rly`, which prints to stdout
+/// (and stderr)
+pub fn for_config(config: &Config) -> Box<dyn OutputSink> {
+    let groups = (config.group && !config.tui)
+        .then(|| OutputGroups::new(config.commands.len(), config.group_order));
+
+    match config.output_format {
+        OutputFormat::Text if config.raw => Box::new(RawSink::new()),
+        OutputFormat::Text => Box::new(TerminalSink {
+            lines: LineWriter {
+                out: Box::new(io::stdout()),
+                err: (!config.merge_stderr).then(|| Box::new(io::stderr()) as Writer),
+                groups,
+            },
+        }),
+        OutputFormat::Jsonl => Box::new(JsonSink {
+            lines: LineWriter {
+                out: Box::new(io::stdout()),
+                err: None,
+                groups,
+            },
+        }),
+    }
+}
+
+/// Writes lines of output, and buffers them by command if output is grouped
+struct LineWriter {
+    out: Writer,
+
+    /// Where lines read from stderr are written to, if they
+    /// are not written to `out` along with everything else
+    err: Option<Writer>,
+
+    groups: Option<OutputGroups>,
+}
+
+impl LineWriter {
+    fn new(out: Writer) -> Self {
+        LineWriter {
+            out,
+            err: None,
+            groups: None,
+        }
+    }
+
+    /// Writes a line, which is grouped with the output of the given command
+    /// (if any). Grouped lines are written to `out`, regardless of their stream.
+    fn write(&mut self, group: Option<usize>, stream: Stream, line: &str) -> io::Result<()> {
+        if let (Some(groups), Some(command_idx)) = (&mut self.groups, group) {
+            groups.push(command_idx, line.to_string());
+            return Ok(());
+        }
+
+        match (&mut self.err, stream) {
+            (Some(err), Stream::Stderr) => writeln!(err, "{}", line),
+            _ => writeln!(self.out, "{}", line),
+        }
+    }
+
+    fn finish(&mut self, command_idx: usize, is_final: bool) -> io::Result<()> {
+        match &mut self.groups {
+            Some(groups) => groups.finish(command_idx, is_final, &mut self.out),
+            None => Ok(()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(groups) = &mut self.groups {
+            groups.flush_all(&mut self.out)?;
+        }
+        if let Some(err) = &mut self.err {
+            err.flush()?;
+        }
+        self.out.flush()
+    }
+}
+
+/// The group an event belongs to. Notices are never grouped.
+fn group(event: &OutputEvent) -> Option<usize> {
+    match event.kind {
+        EventKind::Notice { .. } => None,
+        _ => event.command.map(|command| command.index),
+    }
+}
+
+/// Prints every event as text, i.e. [`OutputEvent::text`]. This is what `rly`
+/// does by default.
+pub struct TerminalSink {
+    lines: LineWriter,
+}
+
+impl TerminalSink {
+    /// Prints to stdout, and output read from stderr to stderr
+    pub fn new() -> Self {
+        TerminalSink {
+            lines: LineWriter {
+                err: Some(Box::new(io::stderr())),
+                ..LineWriter::new(Box::new(io::stdout()))
+            },
+        }
+    }
+
+    /// Prints everything to the given writer
+    pub fn with_writer(out: impl Write + Send + 'static) -> Self {
+        TerminalSink {
+            lines: LineWriter::new(Box::new(out)),
+        }
+    }
+}
+
+impl Default for TerminalSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OutputSink for TerminalSink {
+    fn event(&mut self, event: &OutputEvent) -> io::Result<()> {
+        let Some(text) = event.text else {
+            return Ok(());
+        };
+
+        let stream = match event.kind {
+            EventKind::Output { stream, .. } => stream,
+            _ => Stream::Stdout,
+        };
+        self.lines.write(group(event), stream, text)
+    }
+
+    fn finish(&mut self, command_idx: usize, is_final: bool) -> io::Result<()> {
+        self.lines.finish(command_idx, is_final)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lines.flush()
+    }
+}
+
+/// Prints the output of commands as it is, without any prefixes or
+/// messages, like `--raw` does
+pub struct RawSink {
+    lines: LineWriter,
+}
+
+impl RawSink {
+    /// Prints to stdout and stderr, depending on where the output was read from
+    pub fn new() -> Self {
+        RawSink {
+            lines: LineWriter {
+                err: Some(Box::new(io::stderr())),
+                ..LineWriter::new(Box::new(io::stdout()))
+            },
+        }
+    }
+
+    /// Prints everything to the given writer
+    pub fn with_writer(out: impl Write + Send + 'static) -> Self {
+        RawSink {
+            lines: LineWriter::new(Box::new(out)),
+        }
+    }
+}
+
+impl Default for RawSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OutputSink for RawSink {
+    fn event(&mut self, event: &OutputEvent) -> io::Result<()> {
+        match event.kind {
+            EventKind::Output { stream, line } => self.lines.write(None, stream, line),
+            _ => Ok(()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lines.flush()
+    }
+}
+
+/// Prints every event as a JSON object on a line of its own, like
+/// `--output-format jsonl` does
+pub struct JsonSink {
+    lines: LineWriter,
+}
+
+/// A single line of `--output-format jsonl`
+#[derive(Debug, Default, Serialize)]
+struct JsonEvent<'a> {
+    timestamp: String,
+
+    /// One of `spawn`, `restart`, `output`, `exit`, `status` (messages
+    /// about a command, e.g. that it is ready) or `notice` (messages
+    /// from `rly` itself)
+    event: &'static str,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    index: Option<usize>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pid: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<Stream>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<&'a str>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signal: Option<i32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<&'a str>,
+}
+
+impl JsonSink {
+    /// Prints everything to the given writer, e.g. stdout
+    pub fn new(out: impl Write + Send + 'static) -> Self {
+        JsonSink {
+            lines: LineWriter::new(Box::new(out)),
+        }
+    }
+}
+
+impl OutputSink for JsonSink {
+    fn event(&mut self, event: &OutputEvent) -> io::Result<()> {
+        let mut json = JsonEvent {
+            timestamp: event
+                .timestamp
+                .to_rfc3339_opts(SecondsFormat::Millis, false),
+            name: event.command.map(|command| command.name),
+            index: event.command.map(|command| command.index),
+            pid: event.command.map(|command| command.pid),
+            ..Default::default()
+        };
+
+        match event.kind {
+            EventKind::Spawn => json.event = "spawn",
+            EventKind::Restart => json.event = "restart",
+            EventKind::Output { stream, line } => {
+                json.event = "output";
+                json.stream = Some(stream);
+                json.line = Some(line);
+            }
+            EventKind::Exit { status } => {
+                json.event = "exit";
+                json.exit_code = status.code();
+                json.signal = signal(&status);
+            }
+            EventKind::Status { message } => {
+                json.event = "status";
+                json.message = Some(message);
+            }
+            EventKind::Notice { message } => {
+                // Notices are about `rly` itself, even if caused by a command
+                json = JsonEvent {
+                    timestamp: json.timestamp,
+                    event: "notice",
+                    message: Some(message),
+                    ..Default::default()
+                };
+            }
+        }
+
+        let line = serde_json::to_string(&json).expect("Events can always be serialized");
+        self.lines.write(group(event), Stream::Stdout, &line)
+    }
+
+    fn finish(&mut self, command_idx: usize, is_final: bool) -> io::Result<()> {
+        self.lines.finish(command_idx, is_final)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lines.flush()
+    }
+}
+
+/// Appends every event to a log file, as text without colors, where
+/// every line starts with the time of the event
+pub struct FileSink {
+    lines: LineWriter,
+}
+
+impl FileSink {
+    /// Appends to the file at the given path, which is created if it doesn't exist
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(file))
+    }
+
+    pub fn new(file: File) -> Self {
+        FileSink {
+            lines: LineWriter::new(Box::new(io::BufWriter::new(file))),
+        }
+    }
+}
+
+impl OutputSink for FileSink {
+    fn event(&mut self, event: &OutputEvent) -> io::Result<()> {
+        let message = match (&event.kind, event.command) {
+            (EventKind::Notice { message }, _) => message.to_string(),
+            (EventKind::Spawn, Some(command)) => {
+                format!("[{}] {} started", command.name, command.command)
+            }
+            (EventKind::Restart, Some(command)) => {
+                format!("[{}] {} restarted", command.name, command.command)
+            }
+            (EventKind::Output { line, .. }, Some(command)) => {
+                format!("[{}] {}", command.name, line)
+            }
+            (EventKind::Exit { status }, Some(command)) => {
+                format!(
+                    "[{}] {} exited with {}",
+                    command.name, command.command, status
+                )
+            }
+            (EventKind::Status { message }, Some(command)) => {
+                format!("[{}] {}", command.name, message)
+            }
+            // Only notices may be about no command
+            (_, None) => return Ok(()),
+        };
+
+        let timestamp = event.timestamp.format("%Y-%m-%d %H:%M:%S%.3f");
+        self.lines
+            .write(None, Stream::Stdout, &format!("{} {}", timestamp, message))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lines.flush()
+    }
+}
+
+fn signal(status: &ExitStatus) -> Option<i32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        status.signal()
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = status;
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use chrono::TimeZone;
+
+    use super::*;
+
+    /// Collects everything written to it, while a sink owns a clone of it
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Buffer {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    const API: CommandRef = CommandRef {
+        index: 1,
+        name: "api",
+        command: "cargo run",
+        pid: 42,
+    };
+
+    fn event<'a>(kind: EventKind<'a>, text: Option<&'a str>) -> OutputEvent<'a> {
+        OutputEvent {
+            timestamp: Local.with_ymd_and_hms(2024, 5, 1, 12, 30, 0).unwrap(),
+            command: match kind {
+                EventKind::Notice { .. } => None,
+                _ => Some(API),
+            },
+            kind,
+            text,
+        }
+    }
+
+    fn send(sink: &mut impl OutputSink) {
+        let events = [
+            event(EventKind::Spawn, None),
+            event(
+                EventKind::Output {
+                    stream: Stream::Stderr,
+                    line: "listening",
+                },
+                Some("[api] listening"),
+            ),
+            event(
+                EventKind::Notice {
+                    message: "Terminating all processes..",
+                },
+                Some("Terminating all processes.."),
+            ),
+        ];
+        for event in &events {
+            sink.event(event).unwrap();
+        }
+        sink.flush().unwrap();
+    }
+
+    #[test]
+    fn test_text_sinks() {
+        let out = Buffer::default();
+        send(&mut TerminalSink::with_writer(out.clone()));
+        assert_eq!(
+            "[api] listening\nTerminating all processes..\n",
+            out.contents()
+        );
+
+        let out = Buffer::default();
+        send(&mut RawSink::with_writer(out.clone()));
+        assert_eq!("listening\n", out.contents());
+    }
+
+    #[test]
+    fn test_json_sink() {
+        let out = Buffer::default();
+        send(&mut JsonSink::new(out.clone()));
+
+        let lines: Vec<serde_json::Value> = out
+            .contents()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(3, lines.len());
+        assert_eq!("spawn", lines[0]["event"]);
+        assert_eq!(42, lines[0]["pid"]);
+        assert_eq!("stderr", lines[1]["stream"]);
+        assert_eq!("listening", lines[1]["line"]);
+        assert_eq!("api", lines[1]["name"]);
+        assert_eq!("notice", lines[2]["event"]);
+        assert!(lines[2].get("name").is_none());
+    }
+
+    #[test]
+    fn test_file_sink() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        send(&mut FileSink::create(file.path()).unwrap());
+
+        assert_eq!(
+            "2024-05-01 12:30:00.000 [api] cargo run started\n\
+             2024-05-01 12:30:00.000 [api] listening\n\
+             2024-05-01 12:30:00.000 Terminating all processes..\n",
+            std::fs::read_to_string(file.path()).unwrap()
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use std::process::ExitStatus;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::config::find_command;
+
+/// Decides which of the commands' exit codes determine the exit
+/// code of `rly` itself. See [`crate::cli::Args::success`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SuccessCondition {
+    /// The first command to exit determines the exit code.
+    First,
+
+    /// The last command to exit determines the exit code.
+    Last,
+
+    /// All commands have to exit successfully.
+    All,
+
+    /// Only the command with the given index determines the exit code.
+    Command(usize),
+
+    /// All commands except the one with the given index have to exit
+    /// successfully.
+    AllExcept(usize),
+}
+
+impl SuccessCondition {
+    /// Parses the value given to `--success`. Commands can be referenced
+    /// either by their name or by their index.
+    pub fn parse(input: &str, names: &[String]) -> Result<Self> {
+        let condition = match input {
+            "first" => SuccessCondition::First,
+            "last" => SuccessCondition::Last,
+            "all" => SuccessCondition::All,
+            x => {
+                let (negated, reference) = match x.strip_prefix('!') {
+                    Some(rest) => (true, rest),
+                    None => (false, x),
+                };
+
+                let Some(reference) = reference.strip_prefix("command-") else {
+                    bail!(
+                        "Unknown success condition '{}'. Possible values are: first, last, all, \
+                         command-{{name|index}} and !command-{{name|index}}",
+                        input
+                    );
+                };
+
+                let idx = Self::resolve(reference, names)?;
+                if negated {
+                    SuccessCondition::AllExcept(idx)
+                } else {
+                    SuccessCondition::Command(idx)
+                }
+            }
+        };
+
+        Ok(condition)
+    }
+
+    fn resolve(reference: &str, names: &[String]) -> Result<usize> {
+        find_command(names, reference).ok_or_else(|| {
+            anyhow!(
+                "The success condition references the command '{}', which does not exist",
+                reference
+            )
+        })
+    }
+
+    /// Computes the exit code of `rly` from the exit codes of the commands.
+    ///
+    /// `exit_codes` holds the most recent exit code of each command (or `None`
+    /// if the command never exited), and `exit_order` contains the index of a
+    /// command every time it exited, in the order the exits happened.
+    pub fn exit_code(&self, exit_codes: &[Option<i32>], exit_order: &[usize]) -> i32 {
+        // Commands that never exited cannot be considered successful
+        const NO_EXIT: i32 = 1;
+
+        let code_of = |idx: usize| exit_codes.get(idx).copied().flatten().unwrap_or(NO_EXIT);
+
+        // Returns the code of the first command (in order of their exits) that
+        // failed, or zero if there is none.
+        let first_failure = |except: Option<usize>| {
+            if (0..exit_codes.len())
+                .filter(|idx| Some(*idx) != except)
+                .any(|idx| exit_codes[idx].is_none())
+            {
+                return NO_EXIT;
+            }
+
+            exit_order
+                .iter()
+                .filter(|idx| Some(**idx) != except)
+                .map(|idx| code_of(*idx))
+                .find(|code| *code != 0)
+                .unwrap_or(0)
+        };
+
+        match self {
+            SuccessCondition::First => exit_order.first().map_or(NO_EXIT, |idx| code_of(*idx)),
+            SuccessCondition::Last => exit_order.last().map_or(NO_EXIT, |idx| code_of(*idx)),
+            SuccessCondition::All => first_failure(None),
+            SuccessCondition::Command(idx) => code_of(*idx),
+            SuccessCondition::AllExcept(idx) => first_failure(Some(*idx)),
+        }
+    }
+}
+
+/// Converts the given [`ExitStatus`] into an exit code the way a shell would,
+/// meaning that processes terminated by a signal result in `128 + signal number`.
+pub fn exit_code(status: &ExitStatus) -> i32 {
+    if let Some(code) = status.code() {
+        return code;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names() -> Vec<String> {
+        vec!["api".to_string(), "web".to_string()]
+    }
+
+    #[test]
+    fn test_parse() {
+        let names = names();
+        assert_eq!(
+            SuccessCondition::All,
+            SuccessCondition::parse("all", &names).unwrap()
+        );
+        assert_eq!(
+            SuccessCondition::Command(1),
+            SuccessCondition::parse("command-web", &names).unwrap()
+        );
+        assert_eq!(
+            SuccessCondition::AllExcept(0),
+            SuccessCondition::parse("!command-0", &names).unwrap()
+        );
+        assert!(SuccessCondition::parse("command-2", &names).is_err());
+        assert!(SuccessCondition::parse("some", &names).is_err());
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let codes = [Some(3), Some(0)];
+        let order = [1, 0];
+
+        assert_eq!(0, SuccessCondition::First.exit_code(&codes, &order));
+        assert_eq!(3, SuccessCondition::Last.exit_code(&codes, &order));
+        assert_eq!(3, SuccessCondition::All.exit_code(&codes, &order));
+        assert_eq!(3, SuccessCondition::Command(0).exit_code(&codes, &order));
+        assert_eq!(0, SuccessCondition::AllExcept(0).exit_code(&codes, &order));
+        assert_eq!(1, SuccessCondition::All.exit_code(&[Some(0), None], &[0]));
+    }
+}
@@ -0,0 +1,191 @@
+use std::process::ExitStatus;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+
+/// A single run of a command, i.e. the time between spawning a process and its exit
+struct Run {
+    started: Instant,
+    started_at: DateTime<Local>,
+    exited: Option<(Instant, ExitStatus)>,
+}
+
+/// Records when each command was started and when it exited, including
+/// restarts, for the summary printed by [`crate::cli::Args::timings`].
+pub struct Timings {
+    runs: Vec<Vec<Run>>,
+}
+
+impl Timings {
+    pub fn new(count: usize) -> Self {
+        Timings {
+            runs: (0..count).map(|_| vec![]).collect(),
+        }
+    }
+
+    pub fn started(&mut self, command_idx: usize) {
+        self.runs[command_idx].push(Run {
+            started: Instant::now(),
+            started_at: Local::now(),
+            exited: None,
+        });
+    }
+
+    pub fn exited(&mut self, command_idx: usize, status: ExitStatus) {
+        if let Some(run) = self.runs[command_idx].last_mut() {
+            run.exited = Some((Instant::now(), status));
+        }
+    }
+
+    /// Formats the summary table. Durations span from the first start of a
+    /// command to its last exit, so they include the time spent restarting.
+    pub fn table(&self, names: &[String], commands: &[&str], timestamp_format: &str) -> String {
+        let mut rows = vec![[
+            "name",
+            "command",
+            "started",
+            "duration",
+            "exit status",
+            "restarts",
+        ]
+        .map(String::from)];
+
+        for (idx, runs) in self.runs.iter().enumerate() {
+            let (started, duration, status, restarts) = match (runs.first(), runs.last()) {
+                (Some(first), Some(last)) => (
+                    first.started_at.format(timestamp_format).to_string(),
+                    match last.exited {
+                        Some((exited, _)) => format_duration(exited - first.started),
+                        None => format!("{} (running)", format_duration(first.started.elapsed())),
+                    },
+                    match last.exited {
+                        Some((_, status)) => describe(&status),
+                        None => "-".to_string(),
+                    },
+                    (runs.len() - 1).to_string(),
+                ),
+                _ => (
+                    "-".to_string(),
+                    "-".to_string(),
+                    "not started".to_string(),
+                    "0".to_string(),
+                ),
+            };
+
+            rows.push([
+                names[idx].clone(),
+                commands[idx].to_string(),
+                started,
+                duration,
+                status,
+                restarts,
+            ]);
+        }
+
+        format_table(&rows)
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.2}s", duration.as_secs_f64())
+}
+
+fn describe(status: &ExitStatus) -> String {
+    if let Some(code) = status.code() {
+        return code.to_string();
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return format!("signal {}", signal);
+        }
+    }
+
+    status.to_string()
+}
+
+/// Pads all columns to the same width. The last column is right-aligned,
+/// as it holds numbers.
+fn format_table<const N: usize>(rows: &[[String; N]]) -> String {
+    let mut widths = [0; N];
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut table = String::new();
+    for row in rows {
+        let cells: Vec<_> = row
+            .iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(idx, (cell, width))| {
+                if idx == N - 1 {
+                    format!("{:>width$}", cell)
+                } else {
+                    format!("{:<width$}", cell)
+                }
+            })
+            .collect();
+        table.push_str(cells.join("  ").trim_end());
+        table.push('\n');
+    }
+
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_table() {
+        let rows = [
+            ["name", "duration", "restarts"].map(String::from),
+            ["api", "12.50s", "0"].map(String::from),
+            ["migrate", "0.30s", "12"].map(String::from),
+        ];
+
+        let expected = "\
+name     duration  restarts
+api      12.50s           0
+migrate  0.30s           12
+";
+        assert_eq!(expected, format_table(&rows));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_table() {
+        let names = ["build".to_string(), "lint".to_string()];
+        let commands = ["make", "make lint"];
+
+        let mut timings = Timings::new(2);
+        timings.started(0);
+        timings.exited(0, std::process::Command::new("false").status().unwrap());
+        timings.started(0);
+        timings.exited(0, std::process::Command::new("true").status().unwrap());
+
+        let table = timings.table(&names, &commands, "%Y");
+        let lines: Vec<Vec<_>> = table
+            .lines()
+            .map(|line| {
+                line.split("  ")
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .collect()
+            })
+            .collect();
+
+        assert_eq!(3, lines.len());
+        assert_eq!(["build", "make"], lines[1][..2]);
+        assert_eq!(["0", "1"], lines[1][4..]);
+        assert_eq!(
+            ["lint", "make lint", "-", "-", "not started", "0"],
+            lines[2][..]
+        );
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::VecDeque;
+use std::io::IsTerminal;
+use std::time::Duration;
+
+use ansi_to_tui::IntoText;
+use anyhow::{bail, Context, Result};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use tokio::sync::mpsc;
+
+use crate::pty::visible_line;
+
+/// How many lines of output are kept for each command, and for all of them
+const SCROLLBACK: usize = 10_000;
+
+/// How often the terminal UI is redrawn, e.g. to update the uptimes
+pub const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+const SIDEBAR_WIDTH: u16 = 36;
+
+const HELP: &str = " ↑↓ select  PgUp/PgDn scroll  r restart  x stop  s start  q quit ";
+
+/// Everything that would have been printed, collected for the terminal UI
+pub struct Logs {
+    /// Output of each command, along with the messages about it
+    commands: Vec<VecDeque<Line<'static>>>,
+
+    /// Output of all commands and the messages of `rly` itself
+    all: VecDeque<Line<'static>>,
+}
+
+impl Logs {
+    pub fn new(commands: usize) -> Self {
+        Logs {
+            commands: vec![VecDeque::new(); commands],
+            all: VecDeque::new(),
+        }
+    }
+
+    /// Adds a formatted line, which may contain ANSI escape codes. Lines that
+    /// are not about a specific command only show up in the merged view.
+    pub fn push(&mut self, command_idx: Option<usize>, line: &str) {
+        let line = parse_line(line);
+        if let Some(idx) = command_idx {
+            push_bounded(&mut self.commands[idx], line.clone());
+        }
+        push_bounded(&mut self.all, line);
+    }
+
+    fn view(&self, selected: usize) -> &VecDeque<Line<'static>> {
+        match selected {
+            0 => &self.all,
+            idx => &self.commands[idx - 1],
+        }
+    }
+}
+
+fn push_bounded(lines: &mut VecDeque<Line<'static>>, line: Line<'static>) {
+    if lines.len() == SCROLLBACK {
+        lines.pop_front();
+    }
+    lines.push_back(line);
+}
+
+/// Turns the colors of a line into styles, as the terminal UI
+/// would otherwise show the escape codes themselves
+fn parse_line(line: &str) -> Line<'static> {
+    let line = visible_line(line).replace('\t', "    ");
+    match line.as_bytes().into_text() {
+        Ok(text) => text.lines.into_iter().next().unwrap_or_default(),
+        Err(_) => Line::raw(line),
+    }
+}
+
+/// What a command is doing, as shown in the sidebar and
+/// listed on the control socket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowStatus {
+    Waiting,
+    Starting,
+    Restarting,
+    Running,
+    Ready,
+    Stopping,
+    Exited(Option<i32>),
+    Skipped,
+}
+
+impl RowStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RowStatus::Waiting => "waiting",
+            RowStatus::Starting => "starting",
+            RowStatus::Restarting => "restarting",
+            RowStatus::Running => "running",
+            RowStatus::Ready => "ready",
+            RowStatus::Stopping => "stopping",
+            RowStatus::Exited(_) => "exited",
+            RowStatus::Skipped => "skipped",
+        }
+    }
+}
+
+/// What the sidebar shows about a command
+pub struct Row<'a> {
+    pub name: &'a str,
+    pub status: RowStatus,
+    pub pid: Option<u32>,
+    pub uptime: Option<Duration>,
+}
+
+/// What a key press asks the event loop to do with the selected command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Restart(usize),
+    Stop(usize),
+    Start(usize),
+    Quit,
+}
+
+/// The interactive terminal UI, see [`crate::cli::Args::tui`]. It only shows
+/// the state of the event loop, which drives it using [`Tui::draw`] and
+/// carries out the [`Action`]s of the keys it is given.
+pub struct Tui {
+    terminal: DefaultTerminal,
+
+    /// Index into the sidebar, where the merged view of all
+    /// commands comes first, followed by every command
+    selected: usize,
+
+    /// How many lines the output is scrolled up. At zero, new output is followed.
+    scroll: usize,
+
+    /// Height of the output when it was last drawn, for scrolling by pages
+    page: usize,
+}
+
+impl Tui {
+    /// Switches the terminal to the UI, which is left once it is dropped. Key
+    /// presses are read on a thread of their own, just like input is.
+    pub fn start() -> Result<(Self, mpsc::Receiver<KeyEvent>)> {
+        if !std::io::stdout().is_terminal() {
+            bail!("--tui requires stdout to be a terminal");
+        }
+
+        let terminal = ratatui::try_init().context("Unable to set up the terminal")?;
+        let (tx, rx) = mpsc::channel(16);
+        std::thread::spawn(move || loop {
+            match event::read() {
+                Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                    if tx.blocking_send(key).is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        });
+
+        let tui = Tui {
+            terminal,
+            selected: 0,
+            scroll: 0,
+            page: 0,
+        };
+        Ok((tui, rx))
+    }
+
+    /// Handles a key press, given the number of commands
+    pub fn handle_key(&mut self, key: KeyEvent, commands: usize) -> Option<Action> {
+        let command_idx = self.selected.checked_sub(1);
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Some(Action::Quit)
+            }
+            KeyCode::Char('q') => return Some(Action::Quit),
+            KeyCode::Char('r') => return command_idx.map(Action::Restart),
+            KeyCode::Char('x') => return command_idx.map(Action::Stop),
+            KeyCode::Char('s') => return command_idx.map(Action::Start),
+            KeyCode::Up | KeyCode::Char('k') => self.select(self.selected.saturating_sub(1)),
+            KeyCode::Down | KeyCode::Char('j') => self.select((self.selected + 1).min(commands)),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_add(self.page.max(1)),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_sub(self.page.max(1)),
+            KeyCode::Home => self.scroll = usize::MAX,
+            KeyCode::End => self.scroll = 0,
+            _ => {}
+        }
+
+        None
+    }
+
+    fn select(&mut self, selected: usize) {
+        if selected != self.selected {
+            self.selected = selected;
+            self.scroll = 0;
+        }
+    }
+
+    pub fn draw(&mut self, rows: &[Row], logs: &Logs) -> Result<()> {
+        let Tui {
+            terminal,
+            selected,
+            scroll,
+            page,
+        } = self;
+
+        terminal.draw(|frame| {
+            let [sidebar, output] =
+                Layout::horizontal([Constraint::Length(SIDEBAR_WIDTH), Constraint::Min(0)])
+                    .areas(frame.area());
+
+            draw_sidebar(frame, sidebar, rows, *selected);
+
+            let name = match selected.checked_sub(1) {
+                Some(idx) => rows[idx].name,
+                None => "all",
+            };
+            let block = Block::bordered()
+                .title(format!(" {} ", name))
+                .title_bottom(Line::from(HELP).dim());
+            let inner = block.inner(output);
+            *page = inner.height as usize;
+
+            // Scrolling stops at the first line
+            let lines = logs.view(*selected);
+            *scroll = (*scroll).min(lines.len().saturating_sub(*page));
+            let end = lines.len() - *scroll;
+            let start = end.saturating_sub(*page);
+            let visible: Vec<_> = lines.range(start..end).cloned().collect();
+
+            frame.render_widget(Paragraph::new(visible).block(block), output);
+        })?;
+
+        Ok(())
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        ratatui::restore();
+    }
+}
+
+fn draw_sidebar(frame: &mut Frame, area: Rect, rows: &[Row], selected: usize) {
+    let running = rows
+        .iter()
+        .filter(|row| matches!(row.status, RowStatus::Running | RowStatus::Ready))
+        .count();
+    let mut items = vec![ListItem::new(vec![
+        Line::from("all").bold(),
+        Line::from(format!("  {} of {} running", running, rows.len())).dim(),
+    ])];
+
+    for row in rows {
+        let color = match row.status {
+            RowStatus::Running | RowStatus::Ready => Color::Green,
+            RowStatus::Starting | RowStatus::Restarting | RowStatus::Stopping => Color::Yellow,
+            RowStatus::Exited(Some(code)) if code != 0 => Color::Red,
+            RowStatus::Waiting | RowStatus::Exited(_) | RowStatus::Skipped => Color::DarkGray,
+        };
+        let label = match row.status {
+            RowStatus::Exited(Some(code)) => format!("exited ({})", code),
+            status => status.as_str().to_string(),
+        };
+
+        let mut details = vec![Span::raw("  "), Span::styled(label, color)];
+        if let Some(pid) = row.pid {
+            details.push(Span::raw(format!("  pid {}", pid)).dim());
+        }
+        if let Some(uptime) = row.uptime {
+            details.push(Span::raw(format!("  {}", format_uptime(uptime))).dim());
+        }
+
+        items.push(ListItem::new(vec![
+            Line::from(row.name).bold(),
+            Line::from(details),
+        ]));
+    }
+
+    let list = List::new(items)
+        .block(Block::bordered().title(" rly "))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected(Some(selected));
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+/// Formats an uptime like `1h 02m`, `3m 12s` or `12s`
+pub fn format_uptime(uptime: Duration) -> String {
+    let secs = uptime.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, secs) => format!("{}s", secs),
+        (0, mins, secs) => format!("{}m {:02}s", mins, secs),
+        (hours, mins, _) => format!("{}h {:02}m", hours, mins),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_uptime() {
+        assert_eq!("12s", format_uptime(Duration::from_secs(12)));
+        assert_eq!("3m 02s", format_uptime(Duration::from_secs(182)));
+        assert_eq!("1h 05m", format_uptime(Duration::from_secs(3930)));
+    }
+
+    #[test]
+    fn test_logs() {
+        let mut logs = Logs::new(2);
+        logs.push(Some(1), "\u{1b}[31m[1]\u{1b}[0m failed");
+        logs.push(None, "Terminating all processes..");
+
+        assert_eq!(2, logs.view(0).len());
+        assert!(logs.view(1).is_empty());
+        assert_eq!(Line::from("[1] failed"), plain(&logs.view(2)[0]));
+
+        for idx in 0..SCROLLBACK {
+            logs.push(Some(0), &idx.to_string());
+        }
+        assert_eq!(SCROLLBACK, logs.view(0).len());
+        assert_eq!(Line::from("0"), plain(&logs.view(1)[0]));
+    }
+
+    fn plain(line: &Line) -> Line<'static> {
+        let text: String = line
+            .spans
+            .iter()
+            .map(|span| span.content.as_ref())
+            .collect();
+        Line::from(text)
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::WalkBuilder;
+use log::debug;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+
+/// Changes are collected for this long after the first one, so that e.g. saving
+/// several files at once only restarts a command once.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Ignore files that are respected in the root of the watched directory
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+/// How a command is told about changed files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WatchSignal {
+    /// Terminate the process and start it again. This is the default.
+    #[default]
+    Term,
+
+    /// Send `SIGHUP` to the process, which reloads itself
+    Hup,
+}
+
+impl FromStr for WatchSignal {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.to_ascii_uppercase();
+        match s.strip_prefix("SIG").unwrap_or(&s) {
+            "TERM" => Ok(WatchSignal::Term),
+            "HUP" => Ok(WatchSignal::Hup),
+            _ => bail!(
+                "Unknown watch signal '{}'. Possible values are: SIGTERM, SIGHUP",
+                s
+            ),
+        }
+    }
+}
+
+/// Files to watch for a command, see [`crate::cli::Args::watch`]
+#[derive(Debug)]
+pub struct Watch {
+    /// Directory that is watched recursively, and that patterns are relative to
+    root: PathBuf,
+    patterns: GlobSet,
+    ignore: Gitignore,
+    pub signal: WatchSignal,
+}
+
+/// Receives the changes to files matching a [`Watch`] for as long as it lives
+pub struct Changes {
+    watch: Arc<Watch>,
+    rx: mpsc::UnboundedReceiver<PathBuf>,
+
+    // Stops watching when dropped
+    watcher: RecommendedWatcher,
+
+    /// Directories that are watched, which are all directories below
+    /// [`Watch::root`] that are not ignored
+    dirs: HashSet<PathBuf>,
+}
+
+impl Watch {
+    /// Returns `None` if no patterns are given. Patterns are globs relative to
+    /// `root`, which defaults to the current working directory.
+    pub fn new(
+        patterns: &[String],
+        root: Option<&Path>,
+        signal: WatchSignal,
+    ) -> Result<Option<Self>> {
+        if patterns.is_empty() {
+            return Ok(None);
+        }
+
+        let root = root.unwrap_or(Path::new("."));
+        let root = root
+            .canonicalize()
+            .with_context(|| format!("Failed to watch directory {}", root.display()))?;
+
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            builder
+                .add(Glob::new(pattern).with_context(|| format!("Invalid pattern '{}'", pattern))?);
+        }
+
+        let mut ignore = GitignoreBuilder::new(&root);
+        for file in IGNORE_FILES {
+            let path = root.join(file);
+            if path.is_file() {
+                if let Some(err) = ignore.add(&path) {
+                    return Err(anyhow!(err)).context(format!("Failed to read {}", path.display()));
+                }
+            }
+        }
+
+        Ok(Some(Watch {
+            root,
+            patterns: builder.build()?,
+            ignore: ignore.build()?,
+            signal,
+        }))
+    }
+
+    /// Starts watching for changes
+    pub fn changes(self: &Arc<Self>) -> Result<Changes> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+
+            if matches!(
+                event.kind,
+                EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+            ) {
+                for path in event.paths {
+                    tx.send(path).unwrap_or(());
+                }
+            }
+        })?;
+
+        let mut changes = Changes {
+            watch: Arc::clone(self),
+            rx,
+            watcher,
+            dirs: HashSet::new(),
+        };
+        changes.add_dirs(&self.root)?;
+
+        Ok(changes)
+    }
+
+    /// Returns the path relative to [`Watch::root`] if it matches one of the
+    /// patterns and is not ignored
+    fn relevant(&self, path: &Path) -> Option<PathBuf> {
+        if self.is_ignored(path, path.is_dir()) {
+            return None;
+        }
+
+        let relative = path.strip_prefix(&self.root).ok()?;
+        self.patterns
+            .is_match(relative)
+            .then(|| relative.to_path_buf())
+    }
+
+    /// Whether the path is ignored, or not below [`Watch::root`] at all
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return true;
+        };
+
+        relative
+            .components()
+            .any(|c| c == Component::Normal(".git".as_ref()))
+            || (!relative.as_os_str().is_empty()
+                && self
+                    .ignore
+                    .matched_path_or_any_parents(relative, is_dir)
+                    .is_ignore())
+    }
+}
+
+impl Changes {
+    /// Waits for the next change to relevant files, and returns the relative
+    /// paths of all files that changed within the debounce period.
+    pub async fn next(&mut self) -> Option<Vec<PathBuf>> {
+        let mut changed = vec![];
+
+        while changed.is_empty() {
+            let path = self.rx.recv().await?;
+            self.received(&path, &mut changed);
+        }
+
+        let deadline = tokio::time::Instant::now() + DEBOUNCE;
+        while let Ok(Some(path)) = tokio::time::timeout_at(deadline, self.rx.recv()).await {
+            self.received(&path, &mut changed);
+        }
+
+        Some(changed)
+    }
+
+    /// Collects the changed path if it is relevant. New directories are
+    /// watched from now on, and the relevant files in them count as changed.
+    fn received(&mut self, path: &Path, changed: &mut Vec<PathBuf>) {
+        let mut paths: Vec<_> = self.watch.relevant(path).into_iter().collect();
+
+        if path.is_dir() {
+            if !self.dirs.contains(path) && !self.watch.is_ignored(path, true) {
+                match self.add_dirs(path) {
+                    Ok(files) => paths.extend(files),
+                    Err(err) => debug!("{:?}", err),
+                }
+            }
+        } else if !path.exists() {
+            // Watches of removed directories are gone, and they may be created again
+            self.dirs.retain(|dir| !dir.starts_with(path));
+        }
+
+        for path in paths {
+            if !changed.contains(&path) {
+                changed.push(path);
+            }
+        }
+    }
+
+    /// Watches the given directory and the directories below it, skipping
+    /// ignored ones (e.g. `node_modules`) along with everything in them.
+    /// Returns the relevant files found in these directories.
+    ///
+    /// Directories are watched one by one rather than recursively, as
+    /// recursive watches (e.g. using inotify) would cover ignored directories
+    /// as well, which can easily exceed the limit of watches.
+    fn add_dirs(&mut self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let watch = Arc::clone(&self.watch);
+        let walker = WalkBuilder::new(dir)
+            .standard_filters(false)
+            .filter_entry(move |entry| {
+                let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+                !watch.is_ignored(entry.path(), is_dir)
+            })
+            .build();
+
+        let mut files = vec![];
+        for entry in walker {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    debug!("Failed to look for directories to watch: {}", err);
+                    continue;
+                }
+            };
+
+            let path = entry.path();
+            if !entry.file_type().is_some_and(|t| t.is_dir()) {
+                files.extend(self.watch.relevant(path));
+            } else if self.dirs.insert(path.to_path_buf()) {
+                self.watcher
+                    .watch(path, RecursiveMode::NonRecursive)
+                    .with_context(|| format!("Failed to watch directory {}", path.display()))?;
+            }
+        }
+
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_signal() {
+        assert_eq!(WatchSignal::Term, "SIGTERM".parse().unwrap());
+        assert_eq!(WatchSignal::Hup, "hup".parse().unwrap());
+        assert!("SIGKILL".parse::<WatchSignal>().is_err());
+    }
+
+    #[test]
+    fn test_relevant() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(".gitignore"), "target/\n").unwrap();
+
+        let patterns = ["*.rs".to_string(), "Cargo.toml".to_string()];
+        let watch = Watch::new(&patterns, Some(dir.path()), WatchSignal::Term)
+            .unwrap()
+            .unwrap();
+
+        let root = dir.path().canonicalize().unwrap();
+        assert_eq!(
+            Some(PathBuf::from("src/main.rs")),
+            watch.relevant(&root.join("src/main.rs"))
+        );
+        assert!(watch.relevant(&root.join("Cargo.toml")).is_some());
+        assert!(watch.relevant(&root.join("README.md")).is_none());
+        assert!(watch
+            .relevant(&root.join("target/debug/build.rs"))
+            .is_none());
+        assert!(watch
+            .relevant(&root.join(".git/hooks/pre-commit.rs"))
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        std::fs::create_dir_all(root.join("src/bin")).unwrap();
+        std::fs::create_dir_all(root.join("target/debug")).unwrap();
+
+        let patterns = ["**/*.rs".to_string()];
+        let watch = Watch::new(&patterns, Some(&root), WatchSignal::Term)
+            .unwrap()
+            .unwrap();
+        let mut changes = Arc::new(watch).changes().unwrap();
+
+        // Ignored directories are not watched at all
+        let mut dirs: Vec<_> = changes.dirs.iter().cloned().collect();
+        dirs.sort();
+        assert_eq!(
+            vec![root.clone(), root.join("src"), root.join("src/bin")],
+            dirs
+        );
+
+        // Directories created later on are watched as well
+        std::fs::create_dir_all(root.join("src/new")).unwrap();
+        std::fs::write(root.join("src/new/lib.rs"), "").unwrap();
+        let changed = tokio::time::timeout(Duration::from_secs(5), changes.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(vec![PathBuf::from("src/new/lib.rs")], changed);
+        assert!(changes.dirs.contains(&root.join("src/new")));
+    }
+}
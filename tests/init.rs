@@ -0,0 +1,25 @@
+//! Runs in a process of its own, as the init process reaps
+//! every child process while it is running
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn it_stops_acting_as_init_process_after_running() {
+    let result = rly::Runner::new()
+        .command("sh -c 'sleep 0.2 &'")
+        .init(true)
+        .run()
+        .await
+        .unwrap();
+    assert!(result.success());
+
+    assert!(!nix::sys::prctl::get_child_subreaper().unwrap());
+    for _ in 0..10 {
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "exit 3"])
+            .spawn()
+            .unwrap();
+        // Gives a reaper that is still running the chance to take the exit status
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(Some(3), child.wait().unwrap().code());
+    }
+}
@@ -1,3 +1,7 @@
+#![allow(clippy::needless_borrows_for_generic_args, clippy::useless_format)]
+
+use std::time::Duration;
+
 use crate::util::{assert_eq_lines_unordered, setup};
 
 #[macro_use]
@@ -33,7 +37,7 @@ fn it_runs_a_basic_command() {
     let out = cmd
         .arg("ls .")
         .arg("sleep 0.1; cat some-file; exit 1")
-        .stdout();
+        .err_stdout();
 
     let expected = r#"[0] some-file
 [0] some-other-file
@@ -55,7 +59,7 @@ fn it_supports_names() {
         .args(&["--names", "ls,cat"])
         .arg("ls .")
         .arg("sleep 0.1; cat some-file; exit 1")
-        .stdout();
+        .err_stdout();
 
     let expected = r#"[ls] some-file
 [ls] some-other-file
@@ -78,7 +82,7 @@ fn it_repeats_last_name_if_not_enough_names_are_given() {
         .arg("ls .")
         .arg("sleep 0.2; cat some-file; exit 1")
         .arg("sleep 0.1; cat some-other-file")
-        .stdout();
+        .err_stdout();
 
     let expected = r#"[ls] some-file
 [ls] some-other-file
@@ -238,7 +242,7 @@ fn it_supports_restarting() {
     let out = cmd
         .arg("echo 'hello world'; exit 1")
         .args(&["--restart-tries", "2"])
-        .stdout();
+        .err_stdout();
 
     let expected = format!(
         "[0] hello world
@@ -255,6 +259,92 @@ fn it_supports_restarting() {
     assert_eq_lines_unordered(expected, out);
 }
 
+#[test]
+fn it_supports_restart_policies() {
+    let (_, mut cmd) = setup("it_supports_restart_policies");
+    let (out, code) = cmd
+        .arg("[restart-policy=always,restart-tries=1] echo always")
+        .arg("[restart-policy=never] echo never; exit 1")
+        .stdout_with_code();
+
+    let expected = "[0] always
+[0] echo always exited with exit status: 0
+[0] echo always restarted
+[0] always
+[0] echo always exited with exit status: 0
+[1] never
+[1] echo never; exit 1 exited with exit status: 1
+";
+
+    assert_eq_lines_unordered(expected, out);
+    assert_eq!(1, code);
+}
+
+#[test]
+fn it_backs_off_between_restarts() {
+    let (_, mut cmd) = setup("it_backs_off_between_restarts");
+    let (out, code) = cmd
+        .arg("exit 1")
+        .args(["--restart-tries", "2", "--restart-after", "100"])
+        .args(["--restart-backoff", "2"])
+        .stdout_with_code();
+
+    let expected = "[0] exit 1 exited with exit status: 1
+[0] restarting in 0.1s
+[0] exit 1 restarted
+[0] exit 1 exited with exit status: 1
+[0] restarting in 0.2s
+[0] exit 1 restarted
+[0] exit 1 exited with exit status: 1
+";
+
+    assert_eq!(expected, out);
+    assert_eq!(1, code);
+}
+
+#[test]
+fn it_gives_up_on_crash_loops() {
+    let (_, mut cmd) = setup("it_gives_up_on_crash_loops");
+    let (out, code) = cmd
+        .args(["--names", "api,web"])
+        .args(["--restart-policy", "always", "--crash-loop-failures", "3"])
+        .arg("--kill-others-on-crash-loop")
+        .arg("exit 1")
+        .arg("sleep 1")
+        .stdout_with_code();
+
+    let expected = "[api] exit 1 exited with exit status: 1
+[api] exit 1 restarted
+[api] exit 1 exited with exit status: 1
+[api] exit 1 restarted
+[api] exit 1 exited with exit status: 1
+[api] crash loop detected (3 failures in 30s), giving up
+--> Sending SIGTERM to other processes..
+[web] sleep 1 exited with signal: 15 (SIGTERM)
+";
+
+    assert_eq_lines_unordered(expected, out);
+    assert_eq!(1, code);
+}
+
+#[test]
+fn it_gives_up_on_crash_loops_of_successful_exits() {
+    let (_, mut cmd) = setup("it_gives_up_on_crash_loops_of_successful_exits");
+    let (out, code) = cmd
+        .args(["--restart-policy", "always", "--crash-loop-failures", "2"])
+        .arg("exit 0")
+        .stdout_with_code();
+
+    let expected = "[0] exit 0 exited with exit status: 0
+[0] exit 0 restarted
+[0] exit 0 exited with exit status: 0
+[0] crash loop detected (2 failures in 30s), giving up
+";
+
+    assert_eq_lines_unordered(expected, out);
+    assert_eq!(0, code);
+}
+
 #[test]
 fn it_kills_others_on_exit_0() {
     let (dir, mut cmd) = setup("kill_others_triggers_on_exit_0");
@@ -264,7 +354,7 @@ fn it_kills_others_on_exit_0() {
         .arg("exit 0")
         .arg("sleep 0.2; echo 'should not be printed'")
         .arg("--kill-others")
-        .stdout();
+        .err_stdout();
 
     let expected = format!(
         r#"[0] exit 0 exited with exit status: 0
@@ -306,7 +396,7 @@ fn it_does_kill_others_on_fail_on_exit_code_1() {
         .arg("exit 1")
         .arg("sleep 0.2; echo 'should not be printed'")
         .arg("--kill-others-on-fail")
-        .stdout();
+        .err_stdout();
 
     let expected = format!(
         r#"[0] exit 1 exited with exit status: 1
@@ -350,6 +440,1022 @@ fn it_supports_hiding_by_name() {
     assert_eq_lines_unordered(expected, out);
 }
 
+#[test]
+fn it_exits_with_the_code_of_the_first_failed_command() {
+    let (_, mut cmd) = setup("it_exits_with_the_code_of_the_first_failed_command");
+
+    let (_, code) = cmd
+        .arg("exit 0")
+        .arg("sleep 0.1; exit 3")
+        .arg("sleep 0.2; exit 4")
+        .stdout_with_code();
+
+    assert_eq!(3, code);
+}
+
+#[test]
+fn it_supports_success_conditions() {
+    let commands = ["sleep 0.1; exit 3", "exit 0"];
+    let cases = [
+        ("first", 0),
+        ("last", 3),
+        ("all", 3),
+        ("command-ok", 0),
+        ("command-0", 3),
+        ("!command-fail", 0),
+        ("!command-1", 3),
+    ];
+
+    for (condition, expected_code) in cases {
+        let (_, mut cmd) = setup("it_supports_success_conditions");
+        let (_, code) = cmd
+            .args(commands)
+            .args(["--names", "fail,ok"])
+            .args(["--success", condition])
+            .stdout_with_code();
+
+        assert_eq!(expected_code, code, "--success {}", condition);
+    }
+}
+
+#[test]
+fn it_rejects_unknown_success_conditions() {
+    let (_, mut cmd) = setup("it_rejects_unknown_success_conditions");
+    cmd.arg("exit 0")
+        .args(["--success", "command-foo"])
+        .assert_non_empty_stderr();
+}
+
+#[cfg(not(windows))]
+#[test]
+fn it_maps_signals_to_exit_codes() {
+    let (_, mut cmd) = setup("it_maps_signals_to_exit_codes");
+
+    let (_, code) = cmd.arg("kill -USR1 $$").stdout_with_code();
+
+    assert_eq!(128 + 10, code);
+}
+
+#[test]
+fn it_reads_commands_from_config_file() {
+    let (dir, mut cmd) = setup("it_reads_commands_from_config_file");
+    dir.create_dir("sub");
+    dir.create("sub/some-file", "some-file-contents");
+    dir.create(
+        "rly.toml",
+        r#"
+prefix = "{name}:"
+
+[[commands]]
+name = "cat"
+command = "cat some-file"
+cwd = "sub"
+
+[[commands]]
+command = "echo hidden"
+hide = true
+"#,
+    );
+
+    let out = cmd.stdout();
+
+    let expected = r#"cat: some-file-contents
+cat: cat some-file exited with exit status: 0
+"#;
+
+    assert_eq_lines_unordered(expected, out);
+}
+
+#[test]
+fn it_prefers_command_line_options_over_config_file() {
+    let (dir, mut cmd) = setup("it_prefers_command_line_options_over_config_file");
+    dir.create(
+        "custom.toml",
+        r#"
+prefix = "{name}:"
+restart_tries = 5
+
+[[commands]]
+name = "fail"
+command = "echo failing; exit 1"
+restart_tries = 3
+"#,
+    );
+
+    let (out, code) = cmd
+        .args(["--config", "custom.toml"])
+        .args(["--names", "override"])
+        .args(["--restart-tries", "0"])
+        .stdout_with_code();
+
+    let expected = r#"override: failing
+override: echo failing; exit 1 exited with exit status: 1
+"#;
+
+    assert_eq_lines_unordered(expected, out);
+    assert_eq!(1, code);
+}
+
+#[test]
+fn it_disables_flags_from_config_file() {
+    let (dir, mut cmd) = setup("it_disables_flags_from_config_file");
+    dir.create(
+        "rly.toml",
+        r#"
+kill_others = true
+
+[[commands]]
+command = "exit 0"
+
+[[commands]]
+command = "sleep 0.2; echo 'should be printed'"
+"#,
+    );
+
+    let out = cmd.arg("--no-kill-others").stdout();
+
+    let expected = r#"[0] exit 0 exited with exit status: 0
+[1] should be printed
+[1] sleep 0.2; echo 'should be printed' exited with exit status: 0
+"#;
+
+    assert_eq_lines_unordered(expected, out);
+}
+
+#[test]
+fn it_rejects_invalid_config_files() {
+    let (dir, mut cmd) = setup("it_rejects_invalid_config_files");
+    dir.create("rly.toml", "[[commands]]\nunknown = true\n");
+
+    cmd.assert_non_empty_stderr();
+}
+
+#[test]
+fn it_rejects_missing_working_directories() {
+    let (dir, mut cmd) = setup("it_rejects_missing_working_directories");
+    dir.create(
+        "rly.toml",
+        r#"
+[[commands]]
+command = "sleep 3"
+
+[[commands]]
+name = "missing"
+command = "ls"
+cwd = "nope"
+"#,
+    );
+
+    let out = cmd.assert_err();
+    let err = String::from_utf8_lossy(&out.stderr);
+
+    assert!(err.contains("nope"), "{}", err);
+    assert!(err.contains("'missing'"), "{}", err);
+}
+
+#[cfg(not(windows))]
+#[test]
+fn it_fails_commands_that_cannot_be_started() {
+    let (dir, mut cmd) = setup("it_fails_commands_that_cannot_be_started");
+    dir.create_dir("sub");
+
+    // The working directory is gone by the time the command is started
+    let (out, code) = cmd
+        .arg("rmdir sub")
+        .arg("[cwd=sub,depends-on=0:completed] echo 'should not be printed'")
+        .arg("sh -c 'sleep 3'")
+        .arg("--kill-others-on-fail")
+        .stdout_with_code();
+
+    let expected = r#"[0] rmdir sub exited with exit status: 0
+--> Sending SIGTERM to other processes..
+[2] sh -c 'sleep 3' exited with signal: 15 (SIGTERM)
+"#;
+
+    let (failure, rest): (Vec<_>, Vec<_>) = out.lines().partition(|line| line.starts_with("[1] "));
+    assert_eq!(1, failure.len(), "{}", out);
+    assert!(failure[0].contains("Failed to spawn"), "{}", out);
+    assert_eq_lines_unordered(expected, format!("{}\n", rest.join("\n")));
+    assert_eq!(127, code);
+}
+
+#[test]
+fn it_supports_inline_command_options() {
+    let (_, mut cmd) = setup("it_supports_inline_command_options");
+
+    let (out, code) = cmd
+        .arg("[name=retry,restart-tries=1] echo retry; exit 1")
+        .arg("[prefix=<{index}>,restart-tries=0] sleep 0.2; echo once; exit 2")
+        .args(["--restart-tries", "5"])
+        .stdout_with_code();
+
+    let expected = r#"[retry] retry
+[retry] echo retry; exit 1 exited with exit status: 1
+[retry] echo retry; exit 1 restarted
+[retry] retry
+[retry] echo retry; exit 1 exited with exit status: 1
+<1> once
+<1> sleep 0.2; echo once; exit 2 exited with exit status: 2
+"#;
+
+    assert_eq_lines_unordered(expected, out);
+    assert_eq!(1, code);
+}
+
+#[test]
+fn it_supports_opting_out_of_kill_others() {
+    let (_, mut cmd) = setup("it_supports_opting_out_of_kill_others");
+
+    let out = cmd
+        .arg("[kill-others=false] exit 0")
+        .arg("sleep 0.2; echo 'should be printed'")
+        .arg("--kill-others")
+        .stdout();
+
+    let expected = r#"[0] exit 0 exited with exit status: 0
+[1] should be printed
+[1] sleep 0.2; echo 'should be printed' exited with exit status: 0
+"#;
+
+    assert_eq_lines_unordered(expected, out);
+}
+
+#[test]
+fn it_escalates_kill_signals() {
+    let (_, mut cmd) = setup("it_escalates_kill_signals");
+
+    let (out, code) = cmd
+        .arg("trap '' INT; sleep 3")
+        .arg("sleep 0.2")
+        .arg("--kill-others")
+        .arg("--kill-signal")
+        .arg("SIGINT,200ms,SIGKILL")
+        .stdout_with_code();
+
+    let expected = r#"[1] sleep 0.2 exited with exit status: 0
+--> Sending SIGINT to other processes..
+[0] trap '' INT; sleep 3 still running after 0.2s, sending SIGKILL
+[0] trap '' INT; sleep 3 exited with signal: 9 (SIGKILL)
+"#;
+
+    assert_eq_lines_unordered(expected, out);
+    assert_eq!(137, code);
+}
+
+#[cfg(not(windows))]
+#[test]
+fn it_signals_the_whole_process_group() {
+    let (_, mut cmd) = setup("it_signals_the_whole_process_group");
+
+    let (out, code) = cmd
+        .arg("sh -c 'sleep 3; echo survived'")
+        .arg("sleep 0.2")
+        .arg("--kill-others")
+        .stdout_with_code();
+
+    let expected = r#"[1] sleep 0.2 exited with exit status: 0
+--> Sending SIGTERM to other processes..
+[0] sh -c 'sleep 3; echo survived' exited with signal: 15 (SIGTERM)
+"#;
+
+    assert_eq_lines_unordered(expected, out);
+    assert_eq!(143, code);
+}
+
+#[cfg(not(windows))]
+#[test]
+fn it_kills_processes_left_behind() {
+    let (_, mut cmd) = setup("it_kills_processes_left_behind");
+
+    let started = std::time::Instant::now();
+    let out = cmd.arg("sleep 3 & echo started").stdout();
+
+    let mut lines = out.lines();
+    assert_eq!(Some("[0] started"), lines.next());
+    assert_eq!(
+        Some("[0] sleep 3 & echo started exited with exit status: 0"),
+        lines.next()
+    );
+    assert!(lines.next().is_some_and(|line| line.starts_with(
+        "[0] sleep 3 & echo started left processes behind, sending SIGKILL to process group"
+    )));
+    assert!(started.elapsed() < Duration::from_secs(3));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn it_reaps_orphans_in_init_mode() {
+    let (_, mut cmd) = setup("it_reaps_orphans_in_init_mode");
+
+    // The inner shell exits right away, leaving `sleep` behind as an orphan
+    let out = cmd
+        .arg("--init")
+        .arg(concat!(
+            "sh -c 'sleep 0.3 & echo $! > orphan.pid'; p=$(cat orphan.pid); ",
+            "[ \"$(cut -d' ' -f4 /proc/$p/stat)\" = \"$PPID\" ] && echo adopted; ",
+            "sleep 1; [ -e /proc/$p ] || echo reaped"
+        ))
+        .stdout();
+
+    let mut lines = out.lines();
+    assert_eq!(Some("[0] adopted"), lines.next());
+    assert_eq!(Some("[0] reaped"), lines.next());
+}
+
+#[cfg(not(windows))]
+#[test]
+fn it_forwards_signals_to_commands() {
+    use nix::sys::signal::Signal;
+
+    let (_, mut cmd) = setup("it_forwards_signals_to_commands");
+
+    let (out, _) = cmd
+        .arg("[name=app] trap 'echo got USR1' USR1; while true; do sleep 0.1; done")
+        .arg("[name=other,forward-signals=false] trap 'echo got USR1' USR1; sleep 2")
+        .signal_after(&[
+            (Duration::from_millis(500), Signal::SIGUSR1),
+            (Duration::from_millis(500), Signal::SIGTERM),
+        ]);
+
+    let expected = r#"--> Forwarding SIGUSR1 to app
+[app] got USR1
+SIGTERM received
+Terminating all processes..
+[app] trap 'echo got USR1' USR1; while true; do sleep 0.1; done exited with signal: 15 (SIGTERM)
+[other] trap 'echo got USR1' USR1; sleep 2 exited with signal: 15 (SIGTERM)
+"#;
+
+    assert_eq_lines_unordered(expected, out);
+}
+
+#[cfg(unix)]
+#[test]
+fn it_accepts_control_requests() {
+    use crate::util::control_request;
+
+    let (dir, mut cmd) = setup("it_accepts_control_requests");
+    let socket = dir.path().join("rly.sock");
+
+    let child = cmd
+        .args(["--names", "api,web", "--control-socket"])
+        .arg(&socket)
+        .arg("echo started; sleep 10")
+        .arg("sleep 10")
+        .cmd()
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let list = control_request(&socket, r#"{"command": "list"}"#);
+    assert!(list.starts_with(r#"{"ok":true,"commands":[{"index":0,"name":"api","#));
+    assert!(list.contains(r#""name":"web","command":"sleep 10","status":"running""#));
+
+    assert_eq!(
+        "{\"ok\":false,\"error\":\"Unknown command 'db'\"}\n",
+        control_request(&socket, r#"{"command": "stop", "target": "db"}"#)
+    );
+    assert_eq!(
+        "{\"ok\":true}\n",
+        control_request(&socket, r#"{"command": "restart", "target": "api"}"#)
+    );
+    std::thread::sleep(Duration::from_millis(500));
+
+    let logs = control_request(&socket, r#"{"command": "logs", "target": "api"}"#);
+    assert!(logs.starts_with("{\"ok\":true}\n"));
+    assert_eq!(
+        2,
+        logs.matches(r#""stream":"stdout","line":"started""#)
+            .count()
+    );
+
+    // Stopped commands are not restarted, so rly exits once all are stopped
+    assert_eq!(
+        "{\"ok\":true}\n",
+        control_request(&socket, r#"{"command": "stop"}"#)
+    );
+    let output = child.wait_with_output().unwrap();
+    let out = String::from_utf8_lossy(&output.stdout);
+
+    let mut lines = out.lines();
+    assert_eq!(Some("[api] started"), lines.next());
+    assert_eq!(Some("[api] restarting"), lines.next());
+    assert!(out.contains("[api] echo started; sleep 10 restarted\n[api] started\n"));
+    assert!(out.contains("[web] stopping\n"));
+    assert!(!socket.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn it_controls_rly_with_ctl() {
+    use crate::util::control_request;
+
+    let (dir, mut cmd) = setup("it_controls_rly_with_ctl");
+    let socket = dir.path().join("rly.sock");
+
+    let child = cmd
+        .args(["--names", "api,web", "--control-socket"])
+        .arg(&socket)
+        .arg("echo started; sleep 10")
+        .arg("sleep 10")
+        .cmd()
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Wait for the socket to be created
+    control_request(&socket, r#"{"command": "list"}"#);
+
+    let ctl = |args: &[&str]| {
+        let mut cmd = dir.command();
+        cmd.arg("ctl").arg("--socket").arg(&socket).args(args);
+        cmd
+    };
+
+    let status = ctl(&["status"]).stdout();
+    let mut lines = status.lines();
+    assert_eq!(
+        Some("NAME  STATUS   PID"),
+        lines.next().map(|line| &line[..18])
+    );
+    assert!(lines.next().unwrap().starts_with("api   running  "));
+    assert!(lines.next().unwrap().starts_with("web   running  "));
+    assert_eq!(None, lines.next());
+
+    let output = ctl(&["restart", "db"]).assert_err();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown command 'db'"));
+
+    assert_eq!("", ctl(&["restart", "api"]).stdout());
+    std::thread::sleep(Duration::from_millis(500));
+    assert_eq!("started\nstarted\n", ctl(&["logs", "api"]).stdout());
+    assert_eq!("[api] started\n", ctl(&["logs", "-n", "1"]).stdout());
+
+    assert_eq!("", ctl(&["stop", "--all"]).stdout());
+    let output = child.wait_with_output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("[web] stopping\n"));
+
+    let output = ctl(&["status"]).assert_err();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Is rly running with --control?"));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn it_kills_all_processes_on_second_ctrl_c() {
+    use nix::sys::signal::Signal;
+
+    let (_, mut cmd) = setup("it_kills_all_processes_on_second_ctrl_c");
+
+    let (out, code) = cmd
+        .args(["--kill-timeout", "3000"])
+        .arg("[name=app] trap '' INT TERM; sleep 5")
+        .signal_after(&[
+            (Duration::from_millis(500), Signal::SIGINT),
+            (Duration::from_millis(1700), Signal::SIGINT),
+        ]);
+
+    let expected = r#"Ctrl-C issued
+Terminating all processes..
+--> Waiting for app to exit, killing them in 2s (press Ctrl-C again to kill them now)
+Ctrl-C issued
+--> Killing all processes..
+[app] trap '' INT TERM; sleep 5 exited with signal: 9 (SIGKILL)
+"#;
+
+    assert_eq!(expected, out);
+    assert_eq!(130, code);
+}
+
+#[cfg(not(windows))]
+#[test]
+fn it_does_not_wait_for_pending_restarts_on_ctrl_c() {
+    let (_, mut cmd) = setup("it_does_not_wait_for_pending_restarts_on_ctrl_c");
+
+    let started = std::time::Instant::now();
+    let out = cmd
+        .args(["--restart-tries", "3", "--restart-after", "8000"])
+        .args(["exit 1", "sleep 30"])
+        .kill();
+
+    assert!(out.contains("Terminating all processes.."), "{}", out);
+    assert!(started.elapsed() < Duration::from_secs(3));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn it_exits_right_away_on_second_ctrl_c() {
+    use nix::sys::signal::Signal;
+
+    let (_, mut cmd) = setup("it_exits_right_away_on_second_ctrl_c");
+
+    let started = std::time::Instant::now();
+    let (_, code) = cmd
+        .args(["--restart-tries", "3", "--restart-after", "8000"])
+        .args(["--kill-timeout", "10000"])
+        .arg("exit 1")
+        .arg("[ready-port=1,ready-timeout=10000] trap '' INT TERM; sleep 30")
+        .signal_after(&[
+            (Duration::from_millis(500), Signal::SIGINT),
+            (Duration::from_millis(300), Signal::SIGINT),
+        ]);
+
+    assert_eq!(130, code);
+    assert!(started.elapsed() < Duration::from_secs(3));
+}
+
+#[test]
+fn it_supports_per_command_options_in_config_file() {
+    let (dir, mut cmd) = setup("it_supports_per_command_options_in_config_file");
+    dir.create(
+        "rly.toml",
+        r#"
+restart_tries = 1
+
+[[commands]]
+name = "migrate"
+command = "echo migrating; exit 1"
+restart_tries = 0
+kill_others_on_fail = true
+
+[[commands]]
+name = "api"
+command = "sleep 1"
+"#,
+    );
+
+    let (out, code) = cmd.stdout_with_code();
+
+    let expected = r#"[migrate] migrating
+[migrate] echo migrating; exit 1 exited with exit status: 1
+--> Sending SIGTERM to other processes..
+[api] sleep 1 exited with signal: 15 (SIGTERM)
+"#;
+
+    assert_eq_lines_unordered(expected, out);
+    assert_eq!(1, code);
+}
+
+#[test]
+fn it_starts_commands_after_their_dependencies() {
+    let (_, mut cmd) = setup("it_starts_commands_after_their_dependencies");
+
+    let out = cmd
+        .arg("[name=setup] sleep 0.2; echo 'setup done' > marker")
+        .arg("[name=app,depends-on=setup:completed] cat marker")
+        .arg("[name=db] sleep 0.1")
+        .arg("[name=api,depends-on=db] echo api")
+        .stdout();
+
+    let expected = r#"[setup] sleep 0.2; echo 'setup done' > marker exited with exit status: 0
+[app] setup done
+[app] cat marker exited with exit status: 0
+[db] sleep 0.1 exited with exit status: 0
+[api] api
+[api] echo api exited with exit status: 0
+"#;
+
+    assert_eq_lines_unordered(expected, out);
+}
+
+#[test]
+fn it_skips_commands_whose_dependencies_failed() {
+    let (_, mut cmd) = setup("it_skips_commands_whose_dependencies_failed");
+
+    let (out, code) = cmd
+        .arg("[name=setup] exit 1")
+        .arg("[name=app,depends-on=setup:completed] echo app")
+        .arg("[name=web,depends-on=app] echo web")
+        .stdout_with_code();
+
+    let expected = r#"[setup] exit 1 exited with exit status: 1
+[app] echo app skipped, because its dependency 'setup' failed
+[web] echo web skipped, because its dependency 'app' failed
+"#;
+
+    assert_eq_lines_unordered(expected, out);
+    assert_eq!(1, code);
+}
+
+#[test]
+fn it_rejects_dependency_cycles() {
+    let (_, mut cmd) = setup("it_rejects_dependency_cycles");
+    cmd.arg("[name=a,depends-on=b] echo a")
+        .arg("[name=b,depends-on=a] echo b")
+        .assert_non_empty_stderr();
+}
+
+#[test]
+fn it_rejects_invalid_tui_usage() {
+    let (_, mut cmd) = setup("it_rejects_invalid_tui_usage");
+    cmd.args(["--tui", "echo a"]).assert_non_empty_stderr();
+
+    let (_, mut cmd) = setup("it_rejects_invalid_tui_usage");
+    cmd.args(["--tui", "[raw] echo a"])
+        .assert_non_empty_stderr();
+}
+
+#[test]
+fn it_rejects_log_readiness_of_raw_commands() {
+    let (_, mut cmd) = setup("it_rejects_log_readiness_of_raw_commands");
+    cmd.args(["--raw", "[ready-log=up] echo up"])
+        .assert_non_empty_stderr();
+
+    let (_, mut cmd) = setup("it_rejects_log_readiness_of_raw_commands");
+    cmd.arg("[raw,ready-log=up] echo up")
+        .assert_non_empty_stderr();
+}
+
+#[test]
+fn it_rejects_raw_json_output() {
+    let (_, mut cmd) = setup("it_rejects_raw_json_output");
+    cmd.args(["--raw", "--output-format", "jsonl", "echo a"])
+        .assert_non_empty_stderr();
+
+    let (_, mut cmd) = setup("it_rejects_raw_json_output");
+    cmd.args(["--output-format", "jsonl", "[raw] echo a"])
+        .assert_non_empty_stderr();
+}
+
+/// Replaces the (timing dependent) duration in `ready after 0.3s` lines
+fn strip_ready_times(out: String) -> String {
+    out.lines()
+        .map(|line| match line.find(" ready after ") {
+            Some(idx) => format!("{} ready after Xs\n", &line[..idx]),
+            None => format!("{}\n", line),
+        })
+        .collect()
+}
+
+#[test]
+fn it_waits_for_log_patterns_before_starting_dependents() {
+    let (_, mut cmd) = setup("it_waits_for_log_patterns_before_starting_dependents");
+
+    let out = cmd
+        .arg(
+            "[name=api,ready-log=listening on \\\\d+] sleep 0.2; echo listening on 8080; sleep 0.3",
+        )
+        .arg("[name=web,depends-on=api] echo web")
+        .stdout();
+
+    let expected = r#"[api] listening on 8080
+[api] ready after Xs
+[web] web
+[web] echo web exited with exit status: 0
+[api] sleep 0.2; echo listening on 8080; sleep 0.3 exited with exit status: 0
+"#;
+
+    assert_eq!(expected, strip_ready_times(out));
+}
+
+#[test]
+fn it_waits_for_files_before_starting_dependents() {
+    let (_, mut cmd) = setup("it_waits_for_files_before_starting_dependents");
+
+    let out = cmd
+        .arg("[name=setup,ready-file=ready] sleep 0.2; touch ready; sleep 0.5")
+        .arg("[name=app,depends-on=setup] echo app")
+        .stdout();
+
+    let expected = r#"[setup] ready after Xs
+[app] app
+[app] echo app exited with exit status: 0
+[setup] sleep 0.2; touch ready; sleep 0.5 exited with exit status: 0
+"#;
+
+    assert_eq!(expected, strip_ready_times(out));
+}
+
+#[test]
+fn it_skips_dependents_of_commands_that_never_become_ready() {
+    let (_, mut cmd) = setup("it_skips_dependents_of_commands_that_never_become_ready");
+
+    let (out, code) = cmd
+        .arg("[name=api,ready-file=never,ready-timeout=200] sleep 0.5")
+        .arg("[name=web,depends-on=api] echo web")
+        .stdout_with_code();
+
+    let expected = r#"[api] sleep 0.5 not ready after 0.2s, giving up
+[web] echo web skipped, because its dependency 'api' failed
+[api] sleep 0.5 exited with exit status: 0
+"#;
+
+    assert_eq!(expected, out);
+    assert_eq!(1, code);
+}
+
+#[test]
+fn it_groups_output_by_command() {
+    let (_, mut cmd) = setup("it_groups_output_by_command");
+
+    let out = cmd
+        .args(["--group", "--names", "a,b"])
+        .arg("echo a1; sleep 0.2; echo a2")
+        .arg("sleep 0.1; echo b1; sleep 0.3; echo b2")
+        .stdout();
+
+    let expected = r#"[a] a1
+[a] a2
+[a] echo a1; sleep 0.2; echo a2 exited with exit status: 0
+[b] b1
+[b] b2
+[b] sleep 0.1; echo b1; sleep 0.3; echo b2 exited with exit status: 0
+"#;
+
+    assert_eq!(expected, out);
+}
+
+#[test]
+fn it_groups_output_in_command_order() {
+    let (_, mut cmd) = setup("it_groups_output_in_command_order");
+
+    let out = cmd
+        .args(["--group", "--group-order", "command", "--names", "a,b"])
+        .arg("sleep 0.3; echo a")
+        .arg("echo b")
+        .stdout();
+
+    let expected = r#"[a] a
+[a] sleep 0.3; echo a exited with exit status: 0
+[b] b
+[b] echo b exited with exit status: 0
+"#;
+
+    assert_eq!(expected, out);
+}
+
+#[test]
+fn it_prints_timings() {
+    let (_, mut cmd) = setup("it_prints_timings");
+
+    let (out, code) = cmd
+        .args(["--timings", "--restart-tries", "1", "--names", "ok,fail"])
+        .arg("sleep 0.2")
+        .arg("exit 3")
+        .stdout_with_code();
+
+    let timings: Vec<Vec<_>> = out
+        .lines()
+        .skip_while(|line| *line != "--> Timings:")
+        .skip(1)
+        .map(|line| line.split_whitespace().collect())
+        .collect();
+
+    assert_eq!(3, code);
+    assert_eq!(3, timings.len());
+    assert_eq!(
+        ["name", "command", "started", "duration", "exit", "status", "restarts"],
+        timings[0][..]
+    );
+    assert_eq!(["ok", "sleep", "0.2"], timings[1][..3]);
+    assert_eq!(["0", "0"], timings[1][timings[1].len() - 2..]);
+    assert_eq!(["fail", "exit", "3"], timings[2][..3]);
+    assert_eq!(["3", "1"], timings[2][timings[2].len() - 2..]);
+}
+
+#[test]
+fn it_passes_through_additional_arguments() {
+    let (_, mut cmd) = setup("it_passes_through_additional_arguments");
+
+    let out = cmd
+        .args(["-P", "--names", "one,all,joined"])
+        .arg("echo {1}")
+        .arg("printf '<%s>' {@}")
+        .arg("printf '<%s>' {*}")
+        .args(["--", "my file.ts", "it's"])
+        .stdout();
+
+    let expected = r#"[one] my file.ts
+[one] echo 'my file.ts' exited with exit status: 0
+[all] <my file.ts><it's>
+[all] printf '<%s>' 'my file.ts' 'it'\''s' exited with exit status: 0
+[joined] <my file.ts it's>
+[joined] printf '<%s>' 'my file.ts it'\''s' exited with exit status: 0
+"#;
+
+    assert_eq_lines_unordered(expected, out);
+}
+
+#[test]
+fn it_treats_additional_arguments_as_commands_without_passthrough() {
+    let (_, mut cmd) = setup("it_treats_additional_arguments_as_commands_without_passthrough");
+
+    let out = cmd.arg("echo a").args(["--", "echo b"]).stdout();
+
+    let expected = r#"[0] a
+[0] echo a exited with exit status: 0
+[1] b
+[1] echo b exited with exit status: 0
+"#;
+
+    assert_eq_lines_unordered(expected, out);
+}
+
+#[test]
+fn it_forwards_stderr_to_stderr() {
+    let (_, mut cmd) = setup("it_forwards_stderr_to_stderr");
+
+    let out = cmd
+        .args(["--prefix", "[{name}:{stream}]", "--names", "app"])
+        .arg("echo out; echo err >&2")
+        .output();
+
+    assert_eq_lines_unordered(
+        "[app:stdout] out\n[app:stdout] echo out; echo err >&2 exited with exit status: 0\n",
+        String::from_utf8_lossy(&out.stdout),
+    );
+    assert_eq!("[app:stderr] err\n", String::from_utf8_lossy(&out.stderr));
+}
+
+#[test]
+fn it_merges_stderr_into_stdout() {
+    let (_, mut cmd) = setup("it_merges_stderr_into_stdout");
+
+    let out = cmd
+        .args(["--merge-stderr", "--prefix", "[{stream}]"])
+        .arg("echo err >&2")
+        .output();
+
+    assert_eq_lines_unordered(
+        "[stderr] err\n[stdout] echo err >&2 exited with exit status: 0\n",
+        String::from_utf8_lossy(&out.stdout),
+    );
+    assert!(out.stderr.is_empty());
+}
+
+#[test]
+fn it_supports_stream_colors() {
+    let (_, mut cmd) = setup("it_supports_stream_colors");
+
+    let out = cmd
+        .args(["--merge-stderr", "--stream-colors", "reset,dim.red"])
+        .arg("echo out; sleep 0.1; echo err >&2")
+        .output();
+
+    let expected = "[0] out
+[0] \u{1b}[2;31merr\u{1b}[0m
+[0] echo out; sleep 0.1; echo err >&2 exited with exit status: 0
+";
+    assert_eq!(expected, String::from_utf8_lossy(&out.stdout));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn it_attaches_commands_to_a_pty() {
+    let (_, mut cmd) = setup("it_attaches_commands_to_a_pty");
+
+    let out = cmd
+        .args(["--prefix", "[{index}]"])
+        .arg("[pty] test -t 1 && echo tty; printf '10%%\\r50%%\\r100%%\\n'")
+        .arg("test -t 1 || echo pipe")
+        .stdout();
+
+    let expected = r#"[0] tty
+[0] 100%
+[0] test -t 1 && echo tty; printf '10%%\r50%%\r100%%\n' exited with exit status: 0
+[1] pipe
+[1] test -t 1 || echo pipe exited with exit status: 0
+"#;
+
+    assert_eq_lines_unordered(expected, out);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn it_does_not_leak_pseudo_terminals_into_other_commands() {
+    let (_, mut cmd) = setup("it_does_not_leak_pseudo_terminals_into_other_commands");
+
+    let out = cmd
+        .arg("--pty")
+        .arg("sleep 1; ls -l /proc/$$/fd")
+        .arg("[pty=false] sleep 0.3; ls -l /proc/$$/fd")
+        .stdout();
+
+    assert!(out.contains("/dev/pts/"), "{}", out);
+    assert!(!out.contains("ptmx"), "{}", out);
+}
+
+#[test]
+fn it_forwards_input_to_commands() {
+    let (_, mut cmd) = setup("it_forwards_input_to_commands");
+
+    let out = cmd
+        .args(["--handle-input", "--names", "server,web"])
+        .args(["--default-input-target", "web"])
+        .arg("read line; echo server got $line")
+        .arg("cat")
+        .pipe(b"server:rs\nhello\n");
+
+    let expected = r#"[server] server got rs
+[server] read line; echo server got $line exited with exit status: 0
+[web] hello
+[web] cat exited with exit status: 0
+"#;
+
+    assert_eq_lines_unordered(expected, out);
+}
+
+#[test]
+fn it_supports_jsonl_output() {
+    let (_, mut cmd) = setup("it_supports_jsonl_output");
+
+    let out = cmd
+        .args(["--output-format", "jsonl", "--names", "app"])
+        .arg("echo out; echo err >&2; exit 3")
+        .stdout_with_code()
+        .0;
+
+    let events: Vec<serde_json::Value> = out
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    assert_eq!(4, events.len());
+    for event in &events {
+        assert_eq!("app", event["name"]);
+        assert_eq!(0, event["index"]);
+        assert!(event["pid"].as_u64().unwrap() > 0);
+        assert!(event["timestamp"].is_string());
+    }
+
+    assert_eq!("spawn", events[0]["event"]);
+    let mut output: Vec<_> = events[1..3]
+        .iter()
+        .map(|event| {
+            assert_eq!("output", event["event"]);
+            (
+                event["stream"].as_str().unwrap(),
+                event["line"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    output.sort();
+    assert_eq!(vec![("stderr", "err"), ("stdout", "out")], output);
+    assert_eq!("exit", events[3]["event"]);
+    assert_eq!(3, events[3]["exit_code"]);
+}
+
+#[cfg(not(windows))]
+#[test]
+fn it_restarts_watched_commands_on_change() {
+    let (_, mut cmd) = setup("it_restarts_watched_commands_on_change");
+
+    let out = cmd
+        .arg("[name=app,watch=*.txt] sleep 2")
+        .arg("[name=edit] sleep 0.3; echo ignored > a.md; echo changed > a.txt")
+        .kill_after(Duration::from_millis(1000));
+
+    let expected = r#"[edit] sleep 0.3; echo ignored > a.md; echo changed > a.txt exited with exit status: 0
+[app] a.txt changed, restarting
+[app] sleep 2 exited with signal: 15 (SIGTERM)
+[app] sleep 2 restarted
+Ctrl-C issued
+Terminating all processes..
+[app] sleep 2 exited with signal: 15 (SIGTERM)
+"#;
+
+    assert_eq!(expected, out);
+}
+
+#[cfg(not(windows))]
+#[test]
+fn it_sends_the_watch_signal_to_the_whole_process_group() {
+    let (_, mut cmd) = setup("it_sends_the_watch_signal_to_the_whole_process_group");
+
+    // The process reloading itself is started by the shell of the command
+    let out = cmd
+        .arg(
+            "[name=app,watch=*.txt,watch-signal=SIGHUP] sh -c 'trap \"echo reloaded\" HUP; \
+             while :; do sleep 0.1; done'",
+        )
+        .arg("[name=edit] sleep 0.3; touch a.txt")
+        .kill_after(Duration::from_millis(1000));
+
+    assert!(out.contains("[app] a.txt changed, reloading\n"), "{}", out);
+    assert!(out.contains("[app] reloaded\n"), "{}", out);
+}
+
+#[cfg(not(windows))]
+#[test]
+fn it_keeps_watching_commands_that_exited() {
+    let (_, mut cmd) = setup("it_keeps_watching_commands_that_exited");
+
+    let out = cmd
+        .arg("[name=build,watch=*.txt] echo building")
+        .arg("[name=edit] sleep 0.3; touch b.txt")
+        .kill_after(Duration::from_millis(1000));
+
+    let expected = r#"[build] building
+[build] echo building exited with exit status: 0
+[edit] sleep 0.3; touch b.txt exited with exit status: 0
+[build] b.txt changed, restarting
+[build] building
+[build] echo building exited with exit status: 0
+[build] echo building restarted
+Ctrl-C issued
+Terminating all processes..
+"#;
+
+    assert_eq_lines_unordered(expected, out);
+}
+
 #[cfg(not(windows))]
 #[test]
 fn it_detects_ctrl_c() {
@@ -374,3 +1480,125 @@ fn escape_debug_by_line(s: impl AsRef<str>) -> String {
         .collect::<Vec<_>>()
         .join("\n")
 }
+
+#[cfg(unix)]
+#[tokio::test]
+async fn it_runs_commands_as_a_library() {
+    use crate::util::SharedOutput;
+
+    let output = SharedOutput::default();
+    let runner = rly::Runner::new()
+        .named("build", "echo compiling; echo warning >&2; exit 3")
+        .named("serve", "sleep 10")
+        .kill_others_on_fail(true)
+        .no_color(true)
+        .output(output.clone());
+
+    // Runs on a task of its own, like any other future that is Send
+    let result = tokio::spawn(runner.run()).await.unwrap().unwrap();
+
+    assert!(!result.success());
+    assert_eq!(3, result.exit_code);
+    assert_eq!(
+        rly::CommandResult {
+            name: "build".to_string(),
+            command: "echo compiling; echo warning >&2; exit 3".to_string(),
+            exit_code: Some(3),
+        },
+        result.commands[0]
+    );
+    assert_eq!(Some(143), result.commands[1].exit_code);
+
+    let expected = r#"[build] compiling
+[build] warning
+[build] echo compiling; echo warning >&2; exit 3 exited with exit status: 3
+--> Sending SIGTERM to other processes..
+[serve] sleep 10 exited with signal: 15 (SIGTERM)
+"#;
+    assert_eq_lines_unordered(expected, output.contents());
+
+    let err = rly::Runner::new().run().await.unwrap_err();
+    assert_eq!("No commands were given", err.to_string());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn it_handles_signals_as_a_library_if_enabled() {
+    use crate::util::SharedOutput;
+
+    let output = SharedOutput::default();
+    let runner = rly::Runner::new()
+        .named(
+            "app",
+            "trap 'echo reloaded; exit 0' USR1; echo started; while true; do sleep 0.1; done",
+        )
+        .handle_signals(true)
+        .no_color(true)
+        .output(output.clone());
+    let result = tokio::spawn(runner.run());
+
+    while !output.contents().contains("started") {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    nix::sys::signal::raise(nix::sys::signal::Signal::SIGUSR1).unwrap();
+
+    let result = result.await.unwrap().unwrap();
+    assert_eq!(Some(0), result.commands[0].exit_code);
+    assert!(output.contents().contains("[app] reloaded"));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn it_reports_events_to_custom_sinks() {
+    use std::sync::{Arc, Mutex};
+
+    use rly::{EventKind, OutputEvent, OutputSink, Stream};
+
+    /// Keeps a summary of every event, e.g. `output api stderr: warning`
+    #[derive(Clone, Default)]
+    struct Events(Arc<Mutex<Vec<String>>>);
+
+    impl OutputSink for Events {
+        fn event(&mut self, event: &OutputEvent) -> std::io::Result<()> {
+            let name = event.command.map(|command| command.name).unwrap_or("rly");
+            let summary = match &event.kind {
+                EventKind::Spawn => format!("spawn {}", name),
+                EventKind::Restart => format!("restart {}", name),
+                EventKind::Output { stream, line } => {
+                    let stream = match stream {
+                        Stream::Stdout => "stdout",
+                        Stream::Stderr => "stderr",
+                    };
+                    format!("output {} {}: {}", name, stream, line)
+                }
+                EventKind::Exit { status } => format!("exit {} {:?}", name, status.code()),
+                EventKind::Status { message } => format!("status {}: {}", name, message),
+                EventKind::Notice { message } => format!("notice {}: {}", name, message),
+            };
+            self.0.lock().unwrap().push(summary);
+            Ok(())
+        }
+    }
+
+    let events = Events::default();
+    let result = rly::Runner::new()
+        .named("api", "echo warning >&2; exit 1")
+        .restart_tries(1)
+        .sink(events.clone())
+        .run()
+        .await
+        .unwrap();
+
+    assert_eq!(Some(1), result.commands[0].exit_code);
+    assert_eq!(
+        vec![
+            "spawn api",
+            "output api stderr: warning",
+            "exit api Some(1)",
+            "restart api",
+            "output api stderr: warning",
+            "exit api Some(1)",
+        ],
+        *events.0.lock().unwrap()
+    );
+}
@@ -3,6 +3,12 @@
 // Licensed under the MIT License
 // Copyright (c) 2015 Andrew Gallant
 
+#![allow(
+    clippy::redundant_static_lifetimes,
+    clippy::needless_borrows_for_generic_args
+)]
+#![allow(clippy::empty_line_after_doc_comments)]
+
 use std::ffi::OsStr;
 use std::fs::{self, File};
 use std::io::{self, Write};
@@ -39,6 +45,30 @@ pub fn sort_lines(lines: &str) -> String {
     format!("{}\n", lines.join("\n"))
 }
 
+/// Collects everything written to it, e.g. the output of a [`rly::Runner`],
+/// while it is written to from another task.
+#[derive(Clone, Default)]
+#[allow(dead_code)]
+pub struct SharedOutput(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+#[allow(dead_code)]
+impl SharedOutput {
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 /// Returns true if and only if the given program can be successfully executed
 /// with a `--help` flag.
 #[allow(dead_code)]
@@ -46,6 +76,35 @@ pub fn cmd_exists(program: &str) -> bool {
     Command::new(program).arg("--help").output().is_ok()
 }
 
+/// Sends a request to the control socket of rly (see `--control`), and
+/// returns everything it replied with. Waits for the socket to be created.
+#[cfg(unix)]
+#[allow(dead_code)]
+pub fn control_request(socket: &Path, request: &str) -> String {
+    use std::io::Read;
+    use std::net::Shutdown;
+    use std::os::unix::net::UnixStream;
+
+    let mut tries = 0;
+    let mut stream = loop {
+        match UnixStream::connect(socket) {
+            Ok(stream) => break stream,
+            Err(_) if tries < 100 => {
+                tries += 1;
+                thread::sleep(Duration::from_millis(50));
+            }
+            Err(err) => panic!("Failed to connect to {}: {}", socket.display(), err),
+        }
+    };
+
+    writeln!(stream, "{}", request).unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply).unwrap();
+    reply
+}
+
 /// Dir represents a directory in which tests should be run.
 ///
 /// Directories are created from a global atomic counter to avoid duplicates.
@@ -278,6 +337,19 @@ impl TestCommand {
         }
     }
 
+    /// Runs the command and captures its stdout together with its exit code,
+    /// irrespective of whether the command succeeded or not.
+    pub fn stdout_with_code(&mut self) -> (String, i32) {
+        let o = self.cmd.output().unwrap();
+        let stdout = String::from_utf8_lossy(&o.stdout);
+        match stdout.parse() {
+            Ok(t) => (t, o.status.code().unwrap()),
+            Err(err) => {
+                panic!("could not convert from string: {:?}\n\n{}", err, stdout);
+            }
+        }
+    }
+
     pub fn err_stdout(&mut self) -> String {
         let o = self.assert_err();
         let stdout = String::from_utf8_lossy(&o.stdout);
@@ -318,7 +390,25 @@ impl TestCommand {
     /// Sends SIGINT to process and captures output
     #[cfg(not(windows))]
     pub fn kill(&mut self) -> String {
-        use nix::sys::signal::{self, Signal};
+        // Allow the process sufficient time to start and set up Ctrl-C handler
+        self.kill_after(Duration::from_millis(500))
+    }
+
+    /// Sends SIGINT to process after the given delay and captures output
+    #[cfg(not(windows))]
+    pub fn kill_after(&mut self, delay: Duration) -> String {
+        self.signal_after(&[(delay, nix::sys::signal::Signal::SIGINT)])
+            .0
+    }
+
+    /// Sends the given signals to process, each after the given delay
+    /// (counted from the previous one), and captures output and exit code
+    #[cfg(not(windows))]
+    pub fn signal_after(
+        &mut self,
+        signals: &[(Duration, nix::sys::signal::Signal)],
+    ) -> (String, i32) {
+        use nix::sys::signal;
         use nix::unistd::Pid;
 
         let child = self
@@ -327,17 +417,17 @@ impl TestCommand {
             .spawn()
             .expect("Failed to spawn the process");
 
-        // Allow the process sufficient time to start and set up Ctrl-C handler
-        thread::sleep(Duration::from_millis(500));
-
         let pid = Pid::from_raw(child.id() as i32);
-        signal::kill(pid, Signal::SIGINT).expect("Failed to send SIGINT");
+        for (delay, sig) in signals {
+            thread::sleep(*delay);
+            signal::kill(pid, *sig).expect("Failed to send signal");
+        }
 
         let output = child.wait_with_output().expect("Failed to read stdout");
         let stdout = String::from_utf8_lossy(&output.stdout);
 
         match stdout.parse() {
-            Ok(t) => t,
+            Ok(t) => (t, output.status.code().unwrap()),
             Err(err) => {
                 panic!("could not convert from string: {:?}\n\n{}", err, stdout);
             }